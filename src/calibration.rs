@@ -0,0 +1,90 @@
+use std::{
+    hint::black_box,
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+
+/// Time the benchmark takes on the reference host, which time limits are
+/// written for, unless configured otherwise.
+#[cfg(not(debug_assertions))]
+pub const REFERENCE_TIME: Duration = Duration::from_millis(200);
+
+/// Time the unoptimized benchmark takes on the reference host, about seven
+/// times the release one, so that debug builds measure a plausible factor.
+#[cfg(debug_assertions)]
+pub const REFERENCE_TIME: Duration = Duration::from_millis(1400);
+
+/// Factors a host may plausibly have. Beyond them, the benchmark was most
+/// likely disturbed, or the reference time is wrong.
+pub const FACTOR_RANGE: RangeInclusive<f64> = 0.5..=4.0;
+
+/// Words of the table walked by the benchmark, larger than most L1 caches.
+const TABLE_WORDS: usize = 64 * 1024;
+const ITERATIONS: u64 = 50_000_000;
+/// The fastest round is kept, as it is the least disturbed by other load.
+const ROUNDS: usize = 5;
+
+/// How this host compares to the reference host, measured by [`calibrate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Calibration {
+    /// Fastest time of the benchmark on this host.
+    pub benchmark_time: Duration,
+    /// Time of the benchmark on the reference host.
+    pub reference_time: Duration,
+    /// Factor by which time limits are scaled on this host, above 1 on hosts
+    /// slower than the reference, within [`FACTOR_RANGE`].
+    pub factor: f64,
+    /// Whether the measured factor was out of [`FACTOR_RANGE`] and clamped
+    /// into it.
+    pub outlier: bool,
+}
+
+impl Calibration {
+    /// Compare `benchmark_time` on this host to `reference_time`.
+    pub fn new(benchmark_time: Duration, reference_time: Duration) -> Self {
+        let measured = benchmark_time.as_secs_f64() / reference_time.as_secs_f64();
+        let factor = match measured.is_nan() {
+            true => 1.0,
+            false => measured.clamp(*FACTOR_RANGE.start(), *FACTOR_RANGE.end()),
+        };
+        Self {
+            benchmark_time,
+            reference_time,
+            factor,
+            outlier: factor != measured,
+        }
+    }
+}
+
+/// Run a fixed CPU and memory bound benchmark to measure the speed of the
+/// calling thread's core against `reference_time`, usually [`REFERENCE_TIME`].
+///
+/// Takes about a second, so run it once at startup, on an idle host.
+pub fn calibrate(reference_time: Duration) -> Calibration {
+    let benchmark_time = (0..ROUNDS)
+        .map(|_| {
+            let instant = Instant::now();
+            black_box(benchmark(black_box(ITERATIONS)));
+            instant.elapsed()
+        })
+        .min()
+        .unwrap_or(reference_time);
+    Calibration::new(benchmark_time, reference_time)
+}
+
+/// Dependent arithmetic and table lookups, as typical submissions do.
+fn benchmark(iterations: u64) -> u64 {
+    let mut table = vec![0u64; TABLE_WORDS];
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    for i in 0..iterations {
+        // xorshift64
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let slot = &mut table[state as usize % TABLE_WORDS];
+        *slot = slot.wrapping_add(state ^ i);
+    }
+    table.iter().fold(state, |acc, word| acc ^ word)
+}
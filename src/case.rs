@@ -1,111 +1,384 @@
-use std::{path::PathBuf, time::Duration};
+use std::{
+    fs::Permissions,
+    ops::Deref,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Result;
-use tokio::fs::{create_dir_all, remove_dir_all};
+use tokio::fs::{copy, create_dir_all, remove_dir_all, set_permissions};
+use tokio::process::Command;
 use which::which;
 
 use crate::{
+    checker::Checker,
     compile::{compile, Language},
-    config::{JudgeOptions, TestCase},
-    exec::execute,
+    config::{JudgeOptions, ResourceLimits, RunAs, TestCase},
+    exec::{execute, execute_interactive},
+    interactor::CompiledInteractor,
     judge::{JudgeResult, JudgeStatus},
+    sandbox::Sandbox,
+    seccomp::SeccompPolicy,
+    user::{Credentials, UidLease},
 };
 
+/// Results of `run_test_cases`, one per test case run, with their total score.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TestResults {
+    pub results: Vec<JudgeResult>,
+    /// Sum of the scores of the tests, each weighted by its test case.
+    pub score: f64,
+}
+
+impl TestResults {
+    fn push(&mut self, result: JudgeResult) {
+        self.score += result.score;
+        self.results.push(result);
+    }
+}
+
+impl Deref for TestResults {
+    type Target = Vec<JudgeResult>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.results
+    }
+}
+
+impl IntoIterator for TestResults {
+    type Item = JudgeResult;
+    type IntoIter = std::vec::IntoIter<JudgeResult>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.results.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TestResults {
+    type Item = &'a JudgeResult;
+    type IntoIter = std::slice::Iter<'a, JudgeResult>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.results.iter()
+    }
+}
+
 pub async fn run_test_cases<B, C>(
     language: Language,
     workspace: B,
     source_file_path: B,
     options: JudgeOptions,
-    test_cases: Vec<(C, C)>,
+    sandbox: &dyn Sandbox,
+    test_cases: Vec<C>,
     clean: bool,
-) -> Result<Vec<JudgeResult>>
+) -> Result<TestResults>
 where
     B: Into<PathBuf>,
-    C: Into<PathBuf>,
+    C: Into<TestCase<PathBuf, PathBuf>>,
 {
-    let workspace: PathBuf = workspace.into();
-
-    if !workspace.exists() {
-        create_dir_all(&workspace).await?;
-    }
-
-    let source_file_path = Into::<PathBuf>::into(source_file_path)
-        .to_string_lossy()
-        .to_string();
-    let exec_path = match &language {
-        Language::Python => which("python")?,
-        Language::NodeJs => which("deno")?,
-        Language::Java => which("java")?,
-        _ => workspace.join("out"),
-    };
-
-    if let Err(e) = compile(
+    let prepared = match Prepared::new(
         language,
-        workspace.clone(),
-        &source_file_path,
-        exec_path.to_string_lossy(),
+        workspace.into(),
+        source_file_path.into(),
+        options,
+        sandbox,
+        clean,
     )
-    .await
+    .await?
     {
-        if clean {
-            if let Err(e) = remove_dir_all(workspace).await {
-                anyhow::bail!("Failed to remove workspace: {}", e);
-            }
+        Ok(prepared) => prepared,
+        Err(compile_error) => {
+            return Ok(TestResults {
+                results: vec![compile_error],
+                score: 0.0,
+            })
         }
-        return Ok(vec![JudgeResult {
-            status: JudgeStatus::CompileError {
-                message: e.to_string(),
-            },
-            time_used: Duration::default(),
-            memory_used: 0,
-        }]);
     };
 
-    let py_args = vec![source_file_path.as_str()];
-    let deno_args = vec![
-        "run",
-        format!("--v8-flags=--max-old-space-size={}", options.memory_limit).leak(),
-        "--deny-read=*",
-        "--deny-write=*",
-        "--deny-env=*",
-        "--deny-run=*",
-        "--deny-ffi=*",
-        source_file_path.as_str(),
-    ];
-    let java_args = vec!["Main"];
-    let args = match language {
-        Language::Python => Some(&py_args),
-        Language::NodeJs => Some(&deno_args),
-        Language::Java => Some(&java_args),
-        _ => None,
+    let mut results = TestResults::default();
+    for test_case in test_cases {
+        let result = match prepared.run(test_case.into()).await {
+            Ok(result) => result,
+            Err(e) => return Err(prepared.abandon(clean, e).await),
+        };
+        if prepared.options.fail_fast && !matches!(result.status, JudgeStatus::Accepted) {
+            results.push(result);
+            break;
+        }
+        results.push(result);
+    }
+
+    prepared.finish(clean).await?;
+    Ok(results)
+}
+
+/// A compiled submission, with the checker and interactor judging it, ready
+/// to run test cases.
+pub(crate) struct Prepared<'a> {
+    sandbox: &'a dyn Sandbox,
+    workspace: PathBuf,
+    exec_path: PathBuf,
+    args: Option<Vec<String>>,
+    pub(crate) options: JudgeOptions,
+    // Keeps one uid of a pool for the whole submission, whose group reads the workspace
+    _lease: Option<UidLease>,
+    checker: Option<(Checker, PathBuf, PathBuf)>,
+    interactor: Option<CompiledInteractor>,
+}
+
+impl<'a> Prepared<'a> {
+    /// Compile the submission in `workspace`, returning the result of a
+    /// compile error, after removing the workspaces if `clean`, when it or
+    /// anything else of the preparation fails.
+    pub(crate) async fn new(
+        language: Language,
+        workspace: PathBuf,
+        source_file_path: PathBuf,
+        options: JudgeOptions,
+        sandbox: &'a dyn Sandbox,
+        clean: bool,
+    ) -> Result<Result<Self, JudgeResult>> {
+        if !workspace.exists() {
+            create_dir_all(&workspace).await?;
+        }
+        let prepared = Self::prepare(
+            language,
+            workspace.clone(),
+            source_file_path,
+            options,
+            sandbox,
+            clean,
+        )
+        .await;
+        if prepared.is_err() && clean {
+            // Whatever was created of them
+            let _ = remove_dir_all(sibling(&workspace, ".checker")).await;
+            let _ = remove_dir_all(sibling(&workspace, ".interactor")).await;
+            let _ = remove_dir_all(&workspace).await;
+        }
+        prepared
     }
-    .map(|v| &**v);
 
-    let mut results = vec![];
-    for (input_file, expected_output_file) in test_cases {
-        let result = execute(
-            &workspace,
+    async fn prepare(
+        language: Language,
+        workspace: PathBuf,
+        source_file_path: PathBuf,
+        mut options: JudgeOptions,
+        sandbox: &'a dyn Sandbox,
+        clean: bool,
+    ) -> Result<Result<Self, JudgeResult>> {
+        options
+            .seccomp_policy
+            .get_or_insert_with(|| SeccompPolicy::for_language(language));
+        options
+            .limits
+            .get_or_insert_with(|| ResourceLimits::for_language(language));
+
+        let mut source_file_path = source_file_path;
+        // Interpreted sources are read at runtime, so keep them inside the workspace
+        // where the namespace sandbox can see them.
+        if matches!(language, Language::Python | Language::NodeJs)
+            && !source_file_path.starts_with(&workspace)
+        {
+            if let Some(file_name) = source_file_path.file_name() {
+                let workspace_source = workspace.join(file_name);
+                copy(&source_file_path, &workspace_source).await?;
+                source_file_path = workspace_source;
+            }
+        }
+        let source_file_path = source_file_path.to_string_lossy().to_string();
+        let exec_path = match &language {
+            Language::Python => python_executable().await?,
+            Language::NodeJs => which("deno")?,
+            Language::Java => which("java")?,
+            _ => workspace.join("out"),
+        };
+
+        if let Err(e) = compile(
+            language,
+            workspace.clone(),
+            &source_file_path,
             exec_path.to_string_lossy(),
-            args,
-            &options,
-            TestCase {
-                input_file: input_file.into(),
-                expected_output_file: expected_output_file.into(),
-            },
-            workspace.join("test.out"),
         )
-        .await?;
-        if options.fail_fast && !matches!(result.status, JudgeStatus::Accepted) {
-            results.push(result);
-            break;
+        .await
+        {
+            if clean {
+                if let Err(e) = remove_dir_all(workspace).await {
+                    anyhow::bail!("Failed to remove workspace: {}", e);
+                }
+            }
+            return Ok(Err(JudgeResult {
+                status: JudgeStatus::CompileError {
+                    message: e.to_string(),
+                },
+                ..Default::default()
+            }));
+        };
+
+        let args = match language {
+            Language::Python => Some(vec![source_file_path]),
+            Language::NodeJs => Some(vec![
+                "run".to_string(),
+                format!("--v8-flags=--max-old-space-size={}", options.memory_limit),
+                "--deny-read=*".to_string(),
+                "--deny-write=*".to_string(),
+                "--deny-env=*".to_string(),
+                "--deny-run=*".to_string(),
+                "--deny-ffi=*".to_string(),
+                source_file_path,
+            ]),
+            Language::Java => Some(vec!["-XX:-UsePerfData".to_string(), "Main".to_string()]),
+            _ => None,
+        };
+
+        let lease = match &options.run_as {
+            Some(run_as) => Some(run_as.acquire().await?),
+            None => None,
+        };
+        if let Some(lease) = &lease {
+            let Credentials { uid, gid } = lease.credentials;
+            lease.credentials.share_workspace(&workspace)?;
+            options.run_as = Some(RunAs::User { uid, gid });
         }
-        results.push(result);
+
+        // Beside the workspace, out of the submission's reach in the namespace
+        // sandbox, and kept by the judge, readable by no one else, in the others
+        let checker = match &options.checker {
+            Some(checker) => {
+                let checker_workspace = sibling(&workspace, ".checker");
+                create_dir_all(&checker_workspace).await?;
+                set_permissions(&checker_workspace, Permissions::from_mode(0o700)).await?;
+                let exec_path = checker.compile(&checker_workspace).await?;
+                Some((checker.clone(), checker_workspace, exec_path))
+            }
+            None => None,
+        };
+        let interactor = match &options.interactor {
+            Some(interactor) => {
+                let interactor_workspace = sibling(&workspace, ".interactor");
+                create_dir_all(&interactor_workspace).await?;
+                set_permissions(&interactor_workspace, Permissions::from_mode(0o700)).await?;
+                let interactor = interactor.compile(&interactor_workspace).await?;
+                Some(interactor)
+            }
+            None => None,
+        };
+
+        Ok(Ok(Self {
+            sandbox,
+            workspace,
+            exec_path,
+            args,
+            options,
+            _lease: lease,
+            checker,
+            interactor,
+        }))
     }
 
-    if clean {
-        if let Err(e) = remove_dir_all(workspace).await {
+    /// Run and judge one test case, scoring the result by its weight.
+    pub(crate) async fn run(&self, test_case: TestCase<PathBuf, PathBuf>) -> Result<JudgeResult> {
+        let args: Option<Vec<&str>> = self
+            .args
+            .as_ref()
+            .map(|args| args.iter().map(String::as_str).collect());
+        let args = args.as_deref();
+        let (mut result, output_file) = match &self.interactor {
+            Some(interactor) => {
+                let result = execute_interactive(
+                    self.sandbox,
+                    &self.workspace,
+                    self.exec_path.to_string_lossy(),
+                    args,
+                    &self.options,
+                    test_case.clone(),
+                    interactor,
+                )
+                .await?;
+                (result, interactor.output_file())
+            }
+            None => {
+                let output_file = self.workspace.join("test.out");
+                let result = execute(
+                    self.sandbox,
+                    &self.workspace,
+                    self.exec_path.to_string_lossy(),
+                    args,
+                    &self.options,
+                    test_case.clone(),
+                    output_file.clone(),
+                )
+                .await?;
+                (result, output_file)
+            }
+        };
+        if let Some((checker, checker_workspace, exec_path)) = &self.checker {
+            if result.is_accepted() {
+                let checked = checker
+                    .check(
+                        self.sandbox,
+                        exec_path,
+                        checker_workspace,
+                        &self.options,
+                        &test_case,
+                        &output_file,
+                    )
+                    .await?;
+                result.status = checked.status;
+                result.checker_message = checked.message;
+            }
+        }
+        result.score = result.status.points() * test_case.weight;
+        Ok(result)
+    }
+
+    /// Remove the workspaces if `clean` once `error` stopped the test cases,
+    /// returning it.
+    pub(crate) async fn abandon(self, clean: bool, error: anyhow::Error) -> anyhow::Error {
+        let _ = self.finish(clean).await;
+        error
+    }
+
+    /// Remove the workspaces if `clean`, once every test case has run.
+    pub(crate) async fn finish(self, clean: bool) -> Result<()> {
+        if !clean {
+            return Ok(());
+        }
+        if let Err(e) = remove_dir_all(self.workspace).await {
             anyhow::bail!("Failed to remove workspace: {}", e);
         }
+        if let Some((_, checker_workspace, _)) = self.checker {
+            if let Err(e) = remove_dir_all(checker_workspace).await {
+                anyhow::bail!("Failed to remove checker workspace: {}", e);
+            }
+        }
+        if let Some(interactor) = self.interactor {
+            if let Err(e) = remove_dir_all(interactor.workspace).await {
+                anyhow::bail!("Failed to remove interactor workspace: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The directory named after `workspace` with `suffix`, beside it.
+fn sibling(workspace: &Path, suffix: &str) -> PathBuf {
+    let mut name = workspace.file_name().unwrap_or_default().to_owned();
+    name.push(suffix);
+    workspace.with_file_name(name)
+}
+
+/// Resolve the real Python interpreter behind shims such as pyenv's, which are
+/// shell scripts that would need far more system calls than Python itself.
+async fn python_executable() -> Result<PathBuf> {
+    let output = Command::new(which("python3")?)
+        .args(["-c", "import sys; print(sys.executable)"])
+        .output()
+        .await?;
+    let executable = String::from_utf8(output.stdout)?;
+    match executable.trim() {
+        "" => anyhow::bail!("Failed to locate the Python interpreter"),
+        executable => Ok(PathBuf::from(executable)),
     }
-    Ok(results)
 }
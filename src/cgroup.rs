@@ -0,0 +1,147 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock,
+    },
+    time::Duration,
+};
+
+const CGROUP_MOUNT: &str = "/sys/fs/cgroup";
+const CGROUP_PARENT: &str = "eval-stack";
+const CONTROLLERS: &str = "+cpu +memory +pids";
+
+static PARENT: LazyLock<Option<PathBuf>> = LazyLock::new(prepare_parent);
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Resource usage accounted by the kernel for a single run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CgroupStats {
+    /// Peak memory usage in bytes, from `memory.peak`.
+    pub memory_peak: u64,
+    /// Total CPU time consumed by all tasks, from `cpu.stat`.
+    pub cpu_usage: Duration,
+    /// Maximum number of tasks alive at the same time, from `pids.peak`.
+    pub pids_peak: u64,
+    /// Number of processes killed by the OOM killer, from `memory.events`.
+    pub oom_kills: u64,
+}
+
+/// A transient cgroup v2 created for a single run and removed on drop.
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Create a cgroup with the given memory and task limits.
+    ///
+    /// Returns `None` if cgroup v2 is not available or not writable, in which
+    /// case the caller should fall back to polling `/proc/<pid>/statm`.
+    pub fn create(memory_limit: u64, pids_limit: u64) -> Option<Self> {
+        let parent = PARENT.as_ref()?;
+        let path = parent.join(format!(
+            "run-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir(&path).ok()?;
+        let cgroup = Self { path };
+
+        cgroup.write("memory.max", memory_limit).ok()?;
+        cgroup.write("pids.max", pids_limit).ok()?;
+        // Swap may not be enabled on the host.
+        let _ = cgroup.write("memory.swap.max", 0);
+        Some(cgroup)
+    }
+
+    /// Open `cgroup.procs` for writing, so that a forked child can move itself
+    /// into the cgroup by writing `0` before calling `exec`.
+    pub fn procs_file(&self) -> std::io::Result<fs::File> {
+        fs::OpenOptions::new()
+            .write(true)
+            .open(self.path.join("cgroup.procs"))
+    }
+
+    /// Current memory usage in bytes.
+    pub fn memory_usage(&self) -> Option<u64> {
+        self.read("memory.current")
+    }
+
+    /// Kill every process in the cgroup, including those which left the
+    /// process group of the submission.
+    pub fn kill(&self) {
+        // `cgroup.kill` is only available since Linux 5.14
+        if self.write("cgroup.kill", 1).is_ok() {
+            return;
+        }
+        if let Ok(procs) = fs::read_to_string(self.path.join("cgroup.procs")) {
+            for pid in procs.lines().filter_map(|pid| pid.parse::<i32>().ok()) {
+                unsafe { libc::kill(pid, libc::SIGKILL) };
+            }
+        }
+    }
+
+    pub fn stats(&self) -> CgroupStats {
+        let cpu_usage = fs::read_to_string(self.path.join("cpu.stat"))
+            .ok()
+            .and_then(|stat| find_key(&stat, "usage_usec"))
+            .map(Duration::from_micros)
+            .unwrap_or_default();
+        let oom_kills = fs::read_to_string(self.path.join("memory.events"))
+            .ok()
+            .and_then(|events| find_key(&events, "oom_kill"))
+            .unwrap_or_default();
+        CgroupStats {
+            memory_peak: self.read("memory.peak").unwrap_or_default(),
+            cpu_usage,
+            pids_peak: self.read("pids.peak").unwrap_or_default(),
+            oom_kills,
+        }
+    }
+
+    fn read(&self, file: &str) -> Option<u64> {
+        fs::read_to_string(self.path.join(file))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    fn write(&self, file: &str, value: u64) -> std::io::Result<()> {
+        fs::write(self.path.join(file), value.to_string())
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir(&self.path);
+    }
+}
+
+fn find_key(contents: &str, key: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let (k, v) = line.split_once(' ')?;
+        (k == key).then(|| v.trim().parse().ok())?
+    })
+}
+
+fn prepare_parent() -> Option<PathBuf> {
+    let mount = Path::new(CGROUP_MOUNT);
+    let controllers = fs::read_to_string(mount.join("cgroup.controllers")).ok()?;
+    if !["cpu", "memory", "pids"]
+        .iter()
+        .all(|c| controllers.split_whitespace().any(|x| x == *c))
+    {
+        return None;
+    }
+    fs::write(mount.join("cgroup.subtree_control"), CONTROLLERS).ok()?;
+
+    let parent = mount.join(CGROUP_PARENT);
+    if !parent.exists() {
+        fs::create_dir(&parent).ok()?;
+    }
+    fs::write(parent.join("cgroup.subtree_control"), CONTROLLERS).ok()?;
+    Some(parent)
+}
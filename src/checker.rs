@@ -0,0 +1,284 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+
+use crate::{
+    compile::{compile, Language},
+    config::{JudgeOptions, ResourceLimits, TestCase, TimeLimitMode},
+    exec::execute,
+    judge::{JudgeResult, JudgeStatus},
+    sandbox::Sandbox,
+    seccomp::SeccompPolicy,
+};
+
+/// Exit codes of testlib checkers.
+const EXIT_OK: i32 = 0;
+const EXIT_WRONG_ANSWER: i32 = 1;
+const EXIT_PRESENTATION_ERROR: i32 = 2;
+const EXIT_FAIL: i32 = 3;
+/// `_dirt`, extra content after the answer.
+const EXIT_DIRT: i32 = 4;
+/// `quitp(points)`, the points leading the message.
+const EXIT_POINTS: i32 = 7;
+/// `_unexpected_eof`, the output ending early.
+const EXIT_UNEXPECTED_EOF: i32 = 8;
+
+/// Exit code of `_pc(0)` in testlib, `PC_BASE_EXIT_CODE`, which is 50 when
+/// compiled with `TESTSYS`.
+pub const PC_BASE_EXIT_CODE: i32 = 0;
+
+/// Wall-clock limit of a checker run, regardless of the submission's limits.
+const CHECKER_TIME_LIMIT: Duration = Duration::from_secs(10);
+
+/// A special judge deciding whether an output is accepted, for problems with
+/// more than one correct answer.
+///
+/// Follows the conventions of testlib: the checker runs in the sandbox as
+/// `checker <input> <output> <answer> <result>`, exits with 0 to accept,
+/// 1 for a wrong answer, 2 for a presentation error and 3 when it fails
+/// itself, and writes its message to the result file.
+///
+/// A checker grants part of a test's score by exiting with `_pc(p)`, that is
+/// [`Checker::partial_base`] plus `p` for `p` percent, with `quitp(points)`,
+/// the fraction between 0 and 1 leading its message, or by writing the
+/// fraction to a `score` file in its working directory and exiting with 0.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Checker {
+    /// A compiled language, usually C++ with `testlib.h`.
+    pub language: Language,
+    pub source_file: PathBuf,
+    /// Exit code of `_pc(0)`, [`PC_BASE_EXIT_CODE`] unless the checker was
+    /// compiled otherwise. The exit codes of the other verdicts win over
+    /// `_pc` when they collide.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub partial_base: i32,
+}
+
+/// Outcome of a checker run.
+#[derive(Debug, Clone)]
+pub struct Checked {
+    pub status: JudgeStatus,
+    /// What the checker reported, e.g. `ok 3 numbers`.
+    pub message: Option<String>,
+}
+
+impl Checker {
+    pub fn new<S: Into<PathBuf>>(language: Language, source_file: S) -> Self {
+        Self {
+            language,
+            source_file: source_file.into(),
+            partial_base: PC_BASE_EXIT_CODE,
+        }
+    }
+
+    pub fn partial_base(mut self, partial_base: i32) -> Self {
+        self.partial_base = partial_base;
+        self
+    }
+
+    /// Compile the checker into `workspace`, returning its executable.
+    ///
+    /// Keep it out of the submission's workspace, where a submission could
+    /// replace it.
+    pub async fn compile(&self, workspace: &Path) -> Result<PathBuf> {
+        let exec_path = workspace.join("checker");
+        compile_program(self.language, &self.source_file, &exec_path, "checker").await?;
+        Ok(exec_path)
+    }
+
+    /// Check `output` of a run on `test_case` with the checker compiled at
+    /// `exec_path` into `workspace`, in the sandbox the submission ran in.
+    pub async fn check(
+        &self,
+        sandbox: &dyn Sandbox,
+        exec_path: &Path,
+        workspace: &Path,
+        options: &JudgeOptions,
+        test_case: &TestCase<PathBuf, PathBuf>,
+        output: &Path,
+    ) -> Result<Checked> {
+        // Files out of the workspace may be out of the sandbox's sight
+        let input = workspace.join("input");
+        let answer = workspace.join("answer");
+        let copied_output = workspace.join("output");
+        let result_file = workspace.join("result");
+        fs::copy(&test_case.input_file, &input)?;
+        fs::copy(&test_case.expected_output_file, &answer)?;
+        fs::copy(output, &copied_output)?;
+        let _ = fs::remove_file(&result_file);
+        let _ = fs::remove_file(workspace.join("score"));
+
+        let options = trusted_options(self.language, options, CHECKER_TIME_LIMIT);
+        let paths = [&input, &copied_output, &answer, &result_file]
+            .map(|path| path.to_string_lossy().into_owned());
+        let args: Vec<&str> = paths.iter().map(String::as_str).collect();
+        let run = execute(
+            sandbox,
+            workspace,
+            exec_path.to_string_lossy(),
+            Some(&args),
+            &options,
+            TestCase::new(input.clone(), answer.clone()),
+            workspace.join("stdout"),
+        )
+        .await?;
+        Ok(Checked::from_run(
+            run,
+            workspace,
+            "Checker",
+            self.partial_base,
+        ))
+    }
+}
+
+impl Checked {
+    /// Map the exit code of a testlib program, `what` it is, to a verdict,
+    /// `_pc(p)` exiting with `partial_base + p`.
+    ///
+    /// The message is read from the `result` file in `workspace`, or from
+    /// standard error when the program did not write one, and the points of
+    /// an accepted run from the `score` file.
+    pub(crate) fn from_run(
+        run: JudgeResult,
+        workspace: &Path,
+        what: &str,
+        partial_base: i32,
+    ) -> Self {
+        let message = fs::read_to_string(workspace.join("result"))
+            .ok()
+            .map(|message| message.trim().to_string())
+            .filter(|message| !message.is_empty());
+        let (code, stderr) = match run.status {
+            JudgeStatus::Accepted => (EXIT_OK, String::new()),
+            JudgeStatus::RuntimeError { code, stderr } => (code, stderr),
+            status => {
+                return Self {
+                    status: JudgeStatus::SystemError {
+                        message: format!("{} did not finish: {:?}", what, status),
+                    },
+                    message,
+                }
+            }
+        };
+        let message = message.or_else(|| Some(stderr.trim().to_string()).filter(|s| !s.is_empty()));
+        let status = match code {
+            EXIT_OK => match fs::read_to_string(workspace.join("score")) {
+                Ok(score) => match score.trim().parse::<f64>() {
+                    Ok(points) if (0.0..=1.0).contains(&points) => partially(points),
+                    _ => JudgeStatus::SystemError {
+                        message: format!("{} wrote an invalid score: {}", what, score.trim()),
+                    },
+                },
+                Err(_) => JudgeStatus::Accepted,
+            },
+            EXIT_WRONG_ANSWER => JudgeStatus::WrongAnswer { mismatch: None },
+            EXIT_PRESENTATION_ERROR | EXIT_DIRT | EXIT_UNEXPECTED_EOF => {
+                JudgeStatus::PresentationError
+            }
+            EXIT_FAIL => JudgeStatus::SystemError {
+                message: format!("{} failed: {}", what, message.as_deref().unwrap_or("")),
+            },
+            EXIT_POINTS => match message.as_deref().and_then(quitp_points) {
+                Some(points) if (0.0..=1.0).contains(&points) => partially(points),
+                _ => JudgeStatus::SystemError {
+                    message: format!(
+                        "{} reported invalid points: {}",
+                        what,
+                        message.as_deref().unwrap_or("")
+                    ),
+                },
+            },
+            code if (partial_base..=partial_base + 100).contains(&code) => {
+                partially(f64::from(code - partial_base) / 100.0)
+            }
+            code => JudgeStatus::SystemError {
+                message: format!("{} exited with code {}", what, code),
+            },
+        };
+        Self { status, message }
+    }
+
+    /// Whether the output was rejected, rather than the program failing.
+    pub fn is_rejected(&self) -> bool {
+        matches!(
+            self.status,
+            JudgeStatus::WrongAnswer { .. } | JudgeStatus::PresentationError
+        )
+    }
+}
+
+/// Points of `quitp`, leading its message after testlib's `points` verdict.
+fn quitp_points(message: &str) -> Option<f64> {
+    let message = message.strip_prefix("points").unwrap_or(message);
+    message.split_whitespace().next()?.parse().ok()
+}
+
+/// Verdict of a run earning `points` of a test's score.
+fn partially(points: f64) -> JudgeStatus {
+    if points >= 1.0 {
+        JudgeStatus::Accepted
+    } else {
+        JudgeStatus::PartiallyAccepted { points }
+    }
+}
+
+/// Compile a program of the problem setter, `what` it is, to `exec_path`.
+pub(crate) async fn compile_program(
+    language: Language,
+    source_file: &Path,
+    exec_path: &Path,
+    what: &str,
+) -> Result<()> {
+    if matches!(
+        language,
+        Language::Python | Language::NodeJs | Language::Java
+    ) {
+        anyhow::bail!("The {} must be written in a compiled language", what);
+    }
+    let workspace = exec_path.parent().unwrap_or(Path::new("."));
+    compile(
+        language,
+        workspace,
+        source_file,
+        exec_path.to_string_lossy(),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Failed to compile the {}: {}", what, e))
+}
+
+/// Options for a program of the problem setter written in `language`,
+/// limited to `time_limit` of wall-clock time rather than the submission's
+/// limits.
+pub(crate) fn trusted_options(
+    language: Language,
+    options: &JudgeOptions,
+    time_limit: Duration,
+) -> JudgeOptions {
+    JudgeOptions {
+        time_limit,
+        time_limit_mode: TimeLimitMode::Wall,
+        speed_factor: None,
+        instruction_limit: None,
+        count_instructions: false,
+        // Written by the problem setter, but still sandboxed, and writing its result
+        seccomp_policy: Some(SeccompPolicy::for_language(language).allow_all(&[
+            libc::SYS_openat,
+            libc::SYS_open,
+            libc::SYS_write,
+            libc::SYS_writev,
+        ])),
+        limits: Some(ResourceLimits::for_language(language)),
+        trace_syscalls: None,
+        // As the judge, since the submission's user could otherwise signal it
+        run_as: None,
+        // Its workspace is its own, where it writes its result
+        writable_workspace: true,
+        ..options.clone()
+    }
+}
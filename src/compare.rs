@@ -0,0 +1,234 @@
+use std::{
+    fmt::Debug,
+    fs,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    sync::Arc,
+};
+
+/// Longest token or line quoted in a [`Mismatch`], in characters.
+const EXCERPT_LIMIT: usize = 64;
+
+/// Decides whether the output of a run is an accepted answer.
+pub trait Comparator: Send + Sync + Debug {
+    /// Compare the output in `output` with the expected output in `expected`.
+    fn compare(&self, output: &Path, expected: &Path) -> io::Result<Verdict>;
+}
+
+/// Outcome of a comparison.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Accepted,
+    /// With the first difference, if the comparator can tell.
+    WrongAnswer(Option<Mismatch>),
+    /// The right tokens, laid out wrongly.
+    PresentationError,
+}
+
+/// First difference between an output and the expected output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mismatch {
+    /// Line of the output the difference is on, from 1.
+    pub line: usize,
+    /// Expected token or line, `None` past the end of the expected output.
+    pub expected: Option<String>,
+    /// Token or line of the output, `None` past its end.
+    pub found: Option<String>,
+}
+
+impl Mismatch {
+    fn new(line: usize, expected: Option<&str>, found: Option<&str>) -> Self {
+        Self {
+            line,
+            expected: expected.map(excerpt),
+            found: found.map(excerpt),
+        }
+    }
+}
+
+/// How the output of a run is compared with the expected output.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case", tag = "mode"))]
+pub enum Comparison {
+    /// Byte for byte.
+    Exact,
+    /// Line by line, ignoring whitespace at the end of lines and blank lines
+    /// at the end of the output.
+    #[default]
+    Lines,
+    /// Whitespace separated tokens, regardless of how they are laid out.
+    Tokens,
+    /// Tokens like [`Self::Tokens`], numbers being equal within an absolute
+    /// or a relative error, e.g. `1e-6`. Other tokens compare exactly.
+    Float { absolute: f64, relative: f64 },
+    /// Line by line like [`Self::Lines`], ignoring case.
+    CaseInsensitive,
+    /// The same lines as [`Self::Lines`], in any order. Blank lines are ignored.
+    UnorderedLines,
+    /// Line by line like [`Self::Lines`], an output with the right tokens but
+    /// other whitespace or line breaks being a presentation error rather than
+    /// a wrong answer.
+    Presentation,
+    /// A comparator of the caller's.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(Arc<dyn Comparator>),
+}
+
+impl Comparator for Comparison {
+    fn compare(&self, output: &Path, expected: &Path) -> io::Result<Verdict> {
+        match self {
+            Comparison::Exact => Ok(match fs::read(output)? == fs::read(expected)? {
+                true => Verdict::Accepted,
+                false => Verdict::WrongAnswer(None),
+            }),
+            Comparison::Lines => compare_lines(output, expected, |a, b| a == b),
+            Comparison::Tokens => compare_tokens(output, expected, |a, b| a == b),
+            Comparison::Float { absolute, relative } => compare_tokens(output, expected, |a, b| {
+                match (a.parse::<f64>(), b.parse::<f64>()) {
+                    (Ok(found), Ok(expected)) if found.is_finite() && expected.is_finite() => {
+                        let error = (found - expected).abs();
+                        error <= *absolute || error <= relative * expected.abs()
+                    }
+                    _ => a == b,
+                }
+            }),
+            Comparison::CaseInsensitive => compare_lines(output, expected, |a, b| {
+                a.chars()
+                    .flat_map(char::to_lowercase)
+                    .eq(b.chars().flat_map(char::to_lowercase))
+            }),
+            Comparison::UnorderedLines => {
+                let (output, expected) = read_both(output, expected)?;
+                Ok(match sorted_lines(&output) == sorted_lines(&expected) {
+                    true => Verdict::Accepted,
+                    false => Verdict::WrongAnswer(None),
+                })
+            }
+            Comparison::Presentation => match compare_lines(output, expected, |a, b| a == b)? {
+                Verdict::WrongAnswer(_) => match compare_tokens(output, expected, |a, b| a == b)? {
+                    Verdict::Accepted => Ok(Verdict::PresentationError),
+                    verdict => Ok(verdict),
+                },
+                verdict => Ok(verdict),
+            },
+            Comparison::Custom(comparator) => comparator.compare(output, expected),
+        }
+    }
+}
+
+/// Compare line by line with `eq`, after trimming trailing whitespace. Lines
+/// past the end of the other file must be blank.
+fn compare_lines(
+    output: &Path,
+    expected: &Path,
+    eq: impl Fn(&str, &str) -> bool,
+) -> io::Result<Verdict> {
+    let mut output_lines = lossy_lines(output)?;
+    let mut expected_lines = lossy_lines(expected)?;
+    for line in 1.. {
+        let mismatch = match (output_lines.next(), expected_lines.next()) {
+            (None, None) => break,
+            (Some(found), None) => {
+                let found = found?;
+                let found = trim_line(&found);
+                (!found.is_empty()).then(|| Mismatch::new(line, None, Some(found)))
+            }
+            (None, Some(expected)) => {
+                let expected = expected?;
+                let expected = trim_line(&expected);
+                (!expected.is_empty()).then(|| Mismatch::new(line, Some(expected), None))
+            }
+            (Some(found), Some(expected)) => {
+                let (found, expected) = (found?, expected?);
+                let (found, expected) = (trim_line(&found), trim_line(&expected));
+                (!eq(found, expected)).then(|| Mismatch::new(line, Some(expected), Some(found)))
+            }
+        };
+        if let Some(mismatch) = mismatch {
+            return Ok(Verdict::WrongAnswer(Some(mismatch)));
+        }
+    }
+    Ok(Verdict::Accepted)
+}
+
+/// Compare whitespace separated tokens with `eq`, given the output's token
+/// first.
+fn compare_tokens(
+    output: &Path,
+    expected: &Path,
+    eq: impl Fn(&str, &str) -> bool,
+) -> io::Result<Verdict> {
+    let (output, expected) = read_both(output, expected)?;
+    let mut output_tokens = tokens(&output);
+    let mut expected_tokens = tokens(&expected);
+    // Where the output ended, for tokens it is missing
+    let mut line = 1;
+    loop {
+        let mismatch = match (output_tokens.next(), expected_tokens.next()) {
+            (None, None) => return Ok(Verdict::Accepted),
+            (Some((line, found)), None) => Mismatch::new(line, None, Some(found)),
+            (None, Some((_, expected))) => Mismatch::new(line, Some(expected), None),
+            (Some((found_line, found)), Some((_, expected))) => {
+                line = found_line;
+                if eq(found, expected) {
+                    continue;
+                }
+                Mismatch::new(line, Some(expected), Some(found))
+            }
+        };
+        return Ok(Verdict::WrongAnswer(Some(mismatch)));
+    }
+}
+
+/// Whitespace separated tokens of `text` with the line they are on.
+fn tokens(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .flat_map(|(i, line)| line.split_whitespace().map(move |token| (i + 1, token)))
+}
+
+/// `text` cut to [`EXCERPT_LIMIT`] characters.
+fn excerpt(text: &str) -> String {
+    match text.char_indices().nth(EXCERPT_LIMIT) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Non-blank lines of `text`, trimmed and sorted.
+fn sorted_lines(text: &str) -> Vec<&str> {
+    let mut lines: Vec<_> = text
+        .lines()
+        .map(trim_line)
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.sort_unstable();
+    lines
+}
+
+fn trim_line(line: &str) -> &str {
+    line.trim_end_matches(char::is_whitespace)
+}
+
+/// Lines of the file at `path` like `BufRead::lines`, invalid UTF-8 being
+/// replaced rather than failing.
+fn lossy_lines(path: &Path) -> io::Result<impl Iterator<Item = io::Result<String>>> {
+    let lines = BufReader::new(fs::File::open(path)?).split(b'\n');
+    Ok(lines.map(|line| {
+        line.map(|mut bytes| {
+            if bytes.last() == Some(&b'\r') {
+                bytes.pop();
+            }
+            String::from_utf8_lossy(&bytes).into_owned()
+        })
+    }))
+}
+
+/// Read both files as text, invalid UTF-8 being replaced rather than failing.
+fn read_both(output: &Path, expected: &Path) -> io::Result<(String, String)> {
+    let read =
+        |path: &Path| fs::read(path).map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+    Ok((read(output)?, read(expected)?))
+}
@@ -1,4 +1,122 @@
-use std::{path::PathBuf, time::Duration};
+use std::{ops::Range, path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{
+    checker::Checker, compare::Comparison, compile::Language, interactor::Interactor,
+    scheduler::CoreScheduler, seccomp::SeccompPolicy,
+};
+
+/// Which clock the time limit of a run is measured against.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum TimeLimitMode {
+    /// Wall-clock time elapsed since the process was spawned.
+    #[default]
+    Wall,
+    /// User and system CPU time consumed by the process.
+    Cpu,
+    /// Instructions retired by the process, against `instruction_limit`
+    /// instead of `time_limit`.
+    Instructions,
+}
+
+/// How strongly submissions are isolated from the host.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum SandboxMode {
+    /// Only unshare the mount namespace.
+    #[default]
+    Basic,
+    /// Unshare the user, PID, network, IPC, UTS and mount namespaces and
+    /// `pivot_root` into a minimal read-only root containing the language
+    /// runtime and the workspace.
+    ///
+    /// Works as an unprivileged user through user namespaces.
+    Namespaces,
+}
+
+/// Unprivileged user a submission runs as, switched to once the sandbox is
+/// set up. Requires the judge to run as root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum RunAs {
+    /// A fixed user and group.
+    User { uid: u32, gid: u32 },
+    /// A distinct uid of the range for each concurrent submission, so that
+    /// submissions cannot signal each other or read each other's workspace,
+    /// with the group of the same id, through which it reads its own.
+    Pool { uids: Range<u32> },
+}
+
+/// Resource limits applied to the submission with `setrlimit`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ResourceLimits {
+    /// Maximum stack size in bytes, `None` for unlimited.
+    pub stack: Option<u64>,
+    /// Maximum number of open file descriptors.
+    pub open_files: u64,
+    /// Maximum number of processes and threads of the user running the
+    /// submission, `None` for unlimited.
+    pub processes: Option<u64>,
+    /// Maximum size of the output and any other written file, in bytes.
+    pub file_size: u64,
+    /// CPU time after which the kernel stops the submission, rounded up to
+    /// whole seconds.
+    ///
+    /// Defaults to a second more than `time_limit`, as a backstop to the
+    /// more precise sampling of the judge.
+    pub cpu_time: Option<Duration>,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            stack: Some(8 * 1024 * 1024),
+            open_files: 64,
+            processes: Some(0),
+            file_size: 64 * 1024 * 1024,
+            cpu_time: None,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// Defaults suited to the runtime of `language`.
+    pub fn for_language(language: Language) -> Self {
+        let limits = Self::default();
+        match language {
+            // Deep recursion is common, the memory limit still applies to the stack
+            Language::C | Language::CPP | Language::Rust | Language::Python => Self {
+                stack: None,
+                ..limits
+            },
+            // Threaded runtimes size their own stacks and are bound by the cgroup
+            Language::Golang => Self {
+                open_files: 256,
+                processes: None,
+                ..limits
+            },
+            Language::Java | Language::NodeJs => Self {
+                open_files: 1024,
+                processes: None,
+                ..limits
+            },
+        }
+    }
+
+    /// The `RLIMIT_CPU` in seconds for a run limited to `time_limit`.
+    pub fn cpu_seconds(&self, time_limit: Duration) -> u64 {
+        let (cpu_time, slack) = match self.cpu_time {
+            Some(cpu_time) => (cpu_time, 0),
+            None => (time_limit, 1),
+        };
+        cpu_time.as_secs() + u64::from(cpu_time.subsec_nanos() > 0) + slack
+    }
+}
 
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
@@ -6,8 +124,40 @@ use std::{path::PathBuf, time::Duration};
 pub struct JudgeOptions {
     /// Maximum time limit in seconds.
     pub time_limit: Duration,
+    /// Whether `time_limit` applies to CPU time or wall-clock time.
+    pub time_limit_mode: TimeLimitMode,
+    /// Wall-clock cap for runs limited by CPU time or instructions, so that
+    /// sleeping or blocked programs are still stopped.
+    ///
+    /// Defaults to three times `time_limit`.
+    pub wall_time_limit: Option<Duration>,
+    /// Factor by which `execute` scales the time limits of each run, so that
+    /// they behave alike on hosts of different speeds. Usually the factor of
+    /// [`calibrate`](crate::calibration::calibrate).
+    ///
+    /// Reported in [`JudgeResult::speed_factor`](crate::judge::JudgeResult::speed_factor).
+    /// Instruction budgets are not scaled.
+    pub speed_factor: Option<f64>,
+    /// Instruction budget of runs with [`TimeLimitMode::Instructions`].
+    ///
+    /// Only enforced by sandboxes counting instructions, which also count
+    /// those of threads and children once they exit.
+    pub instruction_limit: Option<u64>,
+    /// Count the instructions retired by each run into
+    /// [`JudgeResult::instructions`](crate::judge::JudgeResult::instructions),
+    /// as always done with [`TimeLimitMode::Instructions`].
+    ///
+    /// Only supported by the native sandbox.
+    pub count_instructions: bool,
+    /// Stop runs whose CPU time does not advance for this long with
+    /// `IdlenessLimitExceeded`, instead of waiting for the time limit.
+    ///
+    /// Disabled by default.
+    pub idle_time_limit: Option<Duration>,
     /// Maximum memory usage in bytes.
     pub memory_limit: u64,
+    /// Maximum number of bytes of standard error kept in verdicts.
+    pub stderr_limit: usize,
     /// Stop running tests after the first failure.
     ///
     /// Enable this option for ICPC mode contests.
@@ -17,16 +167,80 @@ pub struct JudgeOptions {
     pub no_startup_limits: bool,
     /// Run without kernel-level sand-boxing.
     pub unsafe_mode: bool,
+    /// Isolation applied when `unsafe_mode` is disabled.
+    pub sandbox: SandboxMode,
+    /// Run in an empty network namespace, without even a loopback interface,
+    /// regardless of `sandbox` and `seccomp_policy`.
+    ///
+    /// Always the case with [`SandboxMode::Namespaces`].
+    pub isolate_network: bool,
+    /// Let the run write to its workspace, which isolating sandboxes
+    /// otherwise bind read-only, as checkers and interactors write their
+    /// results there.
+    pub writable_workspace: bool,
+    /// System calls the submission may use, unless `no_startup_limits` is set.
+    ///
+    /// Defaults to the policy of the submission's language in `run_test_cases`,
+    /// and to [`SeccompPolicy::strict`] otherwise.
+    pub seccomp_policy: Option<SeccompPolicy>,
+    /// Limits applied with `setrlimit`, including the output limit.
+    ///
+    /// Defaults to the limits of the submission's language in `run_test_cases`,
+    /// and to [`ResourceLimits::default`] otherwise.
+    pub limits: Option<ResourceLimits>,
+    /// How outputs are compared with expected outputs, unless the test case
+    /// sets its own comparison.
+    pub comparator: Comparison,
+    /// Special judge deciding on outputs instead of `comparator`, compiled by
+    /// `run_test_cases`.
+    pub checker: Option<Checker>,
+    /// Program the submission talks to, for interactive problems, compiled by
+    /// `run_test_cases`. Its verdict replaces the comparison of outputs; a
+    /// checker, if set, checks what the interactor wrote instead.
+    pub interactor: Option<Interactor>,
+    /// Trace the submission with `ptrace`, keeping its last system calls in
+    /// [`JudgeResult::trace`](crate::judge::JudgeResult::trace).
+    ///
+    /// Slows runs down, so meant for diagnosing rejected submissions. Only
+    /// supported by the native sandbox with the seccomp policy enabled.
+    pub trace_syscalls: Option<usize>,
+    /// Pin each run to an exclusive core of this scheduler, waiting for one
+    /// to be free.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub scheduler: Option<Arc<CoreScheduler>>,
+    /// User the submission runs as, `None` to keep the judge's user.
+    ///
+    /// `run_test_cases` gives the workspace to this user, so it must be
+    /// reachable by it.
+    pub run_as: Option<RunAs>,
 }
 
 impl Default for JudgeOptions {
     fn default() -> Self {
         Self {
             time_limit: Duration::from_secs(1),
+            time_limit_mode: TimeLimitMode::Wall,
+            wall_time_limit: None,
+            speed_factor: None,
+            instruction_limit: None,
+            count_instructions: false,
+            idle_time_limit: None,
             memory_limit: 128 * 1024 * 1024,
+            stderr_limit: 64 * 1024,
             fail_fast: true,
             no_startup_limits: false,
             unsafe_mode: false,
+            sandbox: SandboxMode::Basic,
+            isolate_network: false,
+            writable_workspace: false,
+            seccomp_policy: None,
+            limits: None,
+            comparator: Comparison::Lines,
+            checker: None,
+            interactor: None,
+            trace_syscalls: None,
+            scheduler: None,
+            run_as: None,
         }
     }
 }
@@ -41,12 +255,138 @@ impl JudgeOptions {
         self.fail_fast(false)
     }
 
+    pub fn cpu_time_limit(mut self, wall_time_limit: Option<Duration>) -> Self {
+        self.time_limit_mode = TimeLimitMode::Cpu;
+        self.wall_time_limit = wall_time_limit;
+        self
+    }
+
+    pub fn speed_factor(mut self, speed_factor: f64) -> Self {
+        self.speed_factor = Some(speed_factor);
+        self
+    }
+
+    /// These options with time limits scaled by `speed_factor`.
+    pub fn scaled(&self) -> Self {
+        let Some(factor) = self.speed_factor else {
+            return self.clone();
+        };
+        let scale = |duration: Duration| duration.mul_f64(factor);
+        let mut limits = self.limits.clone();
+        if let Some(limits) = &mut limits {
+            limits.cpu_time = limits.cpu_time.map(scale);
+        }
+        Self {
+            time_limit: scale(self.time_limit),
+            wall_time_limit: self.wall_time_limit.map(scale),
+            limits,
+            ..self.clone()
+        }
+    }
+
+    /// Limit runs to `budget` retired instructions instead of `time_limit`.
+    pub fn instruction_limit(mut self, budget: u64, wall_time_limit: Option<Duration>) -> Self {
+        self.time_limit_mode = TimeLimitMode::Instructions;
+        self.instruction_limit = Some(budget);
+        self.wall_time_limit = wall_time_limit;
+        self
+    }
+
+    pub fn wall_time_limit(&self) -> Duration {
+        match self.time_limit_mode {
+            TimeLimitMode::Wall => self.time_limit,
+            TimeLimitMode::Cpu | TimeLimitMode::Instructions => {
+                self.wall_time_limit.unwrap_or(self.time_limit * 3)
+            }
+        }
+    }
+
+    /// CPU time after which `RLIMIT_CPU` stops a run, before any slack.
+    pub fn cpu_time_cap(&self) -> Duration {
+        match self.time_limit_mode {
+            TimeLimitMode::Wall | TimeLimitMode::Cpu => self.time_limit,
+            // The budget is checked by the judge, so only cap runaway runs
+            TimeLimitMode::Instructions => self.wall_time_limit(),
+        }
+    }
+
+    pub fn count_instructions(mut self, count_instructions: bool) -> Self {
+        self.count_instructions = count_instructions;
+        self
+    }
+
+    /// Whether runs have their retired instructions counted.
+    pub fn counts_instructions(&self) -> bool {
+        self.count_instructions || self.time_limit_mode == TimeLimitMode::Instructions
+    }
+
+    pub fn idle_time_limit(mut self, idle_time_limit: Duration) -> Self {
+        self.idle_time_limit = Some(idle_time_limit);
+        self
+    }
+
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn comparator(mut self, comparator: Comparison) -> Self {
+        self.comparator = comparator;
+        self
+    }
+
+    pub fn checker(mut self, checker: Checker) -> Self {
+        self.checker = Some(checker);
+        self
+    }
+
+    pub fn interactor(mut self, interactor: Interactor) -> Self {
+        self.interactor = Some(interactor);
+        self
+    }
+
+    pub fn trace_syscalls(mut self, limit: usize) -> Self {
+        self.trace_syscalls = Some(limit);
+        self
+    }
+
+    pub fn scheduler(mut self, scheduler: Arc<CoreScheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    pub fn run_as(mut self, run_as: RunAs) -> Self {
+        self.run_as = Some(run_as);
+        self
+    }
+
+    pub fn sandbox(mut self, sandbox: SandboxMode) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    pub fn isolate_network(mut self, isolate_network: bool) -> Self {
+        self.isolate_network = isolate_network;
+        self
+    }
+
+    pub fn writable_workspace(mut self, writable_workspace: bool) -> Self {
+        self.writable_workspace = writable_workspace;
+        self
+    }
+
+    pub fn seccomp_policy(mut self, seccomp_policy: SeccompPolicy) -> Self {
+        self.seccomp_policy = Some(seccomp_policy);
+        self
+    }
+
     pub fn no_startup_limits(mut self, no_startup_limits: bool) -> Self {
         self.no_startup_limits = no_startup_limits;
         self
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct TestCase<I, O>
 where
     I: Into<PathBuf>,
@@ -54,4 +394,44 @@ where
 {
     pub input_file: I,
     pub expected_output_file: O,
+    /// Overrides [`JudgeOptions::comparator`] for this test case, unless a
+    /// checker is set.
+    pub comparator: Option<Comparison>,
+    /// Score of the test when accepted, 1 by default.
+    pub weight: f64,
+}
+
+impl<I, O> TestCase<I, O>
+where
+    I: Into<PathBuf>,
+    O: Into<PathBuf>,
+{
+    pub fn new(input_file: I, expected_output_file: O) -> Self {
+        Self {
+            input_file,
+            expected_output_file,
+            comparator: None,
+            weight: 1.0,
+        }
+    }
+
+    pub fn comparator(mut self, comparator: Comparison) -> Self {
+        self.comparator = Some(comparator);
+        self
+    }
+
+    pub fn weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+impl<I, O> From<(I, O)> for TestCase<PathBuf, PathBuf>
+where
+    I: Into<PathBuf>,
+    O: Into<PathBuf>,
+{
+    fn from((input_file, expected_output_file): (I, O)) -> Self {
+        Self::new(input_file.into(), expected_output_file.into())
+    }
 }
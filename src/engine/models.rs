@@ -3,7 +3,7 @@ use std::path::PathBuf;
 use serde::{Deserialize, Serialize};
 use surrealdb::sql::Thing;
 
-use crate::compile::Language;
+use crate::{checker::Checker, compare::Comparison, compile::Language, interactor::Interactor};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
@@ -17,6 +17,13 @@ pub enum Status {
 pub struct TestCase {
     pub input: PathBuf,
     pub output: PathBuf,
+    /// Score of the test when accepted.
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,6 +32,16 @@ pub struct Submission {
 
     pub lang: Language,
     pub test_cases: Vec<TestCase>,
+    /// Checker of the problem, if its outputs are not compared as they are.
+    #[serde(default)]
+    pub checker: Option<Checker>,
+    /// Interactor of the problem, if it is interactive.
+    #[serde(default)]
+    pub interactor: Option<Interactor>,
+    /// How outputs of the problem are compared, e.g. telling presentation
+    /// errors apart, when not the default.
+    #[serde(default)]
+    pub comparator: Option<Comparison>,
 
     pub code: String,
     pub status: Status,
@@ -1,32 +1,103 @@
 use std::fs::create_dir_all;
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 use std::time::Duration;
 
 use anyhow::Result;
 use futures::StreamExt;
 use surrealdb::engine::remote::ws::Ws;
 use surrealdb::opt::auth::Root;
+use surrealdb::sql::Thing;
 use surrealdb::{engine::remote::ws::Client, Surreal};
 use surrealdb::{Action, Notification};
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 
-use crate::case::run_test_cases;
+use crate::calibration::{calibrate, Calibration, FACTOR_RANGE, REFERENCE_TIME};
+use crate::case::{run_test_cases, TestResults};
 use crate::compile::Language;
-use crate::config::JudgeOptions;
+use crate::config::{JudgeOptions, SandboxMode, TestCase};
 use crate::engine::models::Status;
 use crate::judge::{JudgeResult, JudgeStatus};
+use crate::sandbox::{self, Native, Sandbox};
+use crate::scheduler::CoreScheduler;
+use crate::utils::{become_subreaper, reap_orphans, ORPHAN_REAP_INTERVAL};
 
 use super::models::Submission;
 
 static DB: LazyLock<Surreal<Client>> = LazyLock::new(Surreal::init);
 
+/// Isolation backend of this deployment, chosen with `EVAL_STACK_SANDBOX`:
+/// `native` (the default), `bwrap` or `nsjail`.
+static SANDBOX: LazyLock<Box<dyn Sandbox>> = LazyLock::new(|| {
+    let sandbox = match std::env::var("EVAL_STACK_SANDBOX") {
+        Ok(name) => sandbox::by_name(&name),
+        Err(_) => Ok(Box::new(Native) as _),
+    };
+    sandbox.unwrap_or_else(|e| panic!("Failed to set up the sandbox: {}", e))
+});
+
+/// Cores runs are pinned to, as many as `EVAL_STACK_SLOTS` or all of the host's.
+static SCHEDULER: LazyLock<Arc<CoreScheduler>> = LazyLock::new(|| {
+    let scheduler = match std::env::var("EVAL_STACK_SLOTS") {
+        Ok(slots) => slots
+            .parse()
+            .map_err(anyhow::Error::from)
+            .and_then(CoreScheduler::new),
+        Err(_) => CoreScheduler::host(),
+    };
+    Arc::new(scheduler.unwrap_or_else(|e| panic!("Failed to set up the scheduler: {}", e)))
+});
+
+/// Speed of this node, measured once at startup, before any run loads it,
+/// against `EVAL_STACK_REFERENCE_TIME` milliseconds or [`REFERENCE_TIME`].
+static CALIBRATION: LazyLock<Calibration> = LazyLock::new(|| {
+    let reference_time = match std::env::var("EVAL_STACK_REFERENCE_TIME") {
+        Ok(millis) => millis
+            .parse()
+            .map(Duration::from_millis)
+            .unwrap_or_else(|e| panic!("Invalid reference time {}: {}", millis, e)),
+        Err(_) => REFERENCE_TIME,
+    };
+    calibrate(reference_time)
+});
+
+/// Name this node is recorded under, `EVAL_STACK_NODE` or the host name.
+static NODE: LazyLock<String> = LazyLock::new(|| {
+    std::env::var("EVAL_STACK_NODE").unwrap_or_else(|_| {
+        std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|name| name.trim().to_string())
+            .unwrap_or_else(|_| "localhost".to_string())
+    })
+});
+
 const LIVE_QUERY: &str = r#"
-LIVE SELECT *, problem.test_cases.{ input: input.path, output: output.path } AS test_cases
+LIVE SELECT *, problem.test_cases.{ input: input.path, output: output.path, weight: weight } AS test_cases, problem.checker AS checker, problem.interactor AS interactor, problem.comparator AS comparator
 FROM submission
 WHERE status = "in_queue"
 "#;
+
+const SUBMISSION_QUERY: &str = r#"
+SELECT *, problem.test_cases.{ input: input.path, output: output.path, weight: weight } AS test_cases, problem.checker AS checker, problem.interactor AS interactor, problem.comparator AS comparator
+FROM ONLY $submission
+"#;
 pub async fn listen_for_submissions() -> Result<()> {
+    // Fail on a misconfigured sandbox before accepting submissions
+    LazyLock::force(&SANDBOX);
+    // Adopt the orphans of submissions, to kill and reap them with their runs
+    become_subreaper()?;
+    tokio::spawn(reap_orphans(ORPHAN_REAP_INTERVAL));
+    println!("Judging on {} slots", SCHEDULER.slots());
+    let calibration = *tokio::task::spawn_blocking(|| LazyLock::force(&CALIBRATION)).await?;
+    println!(
+        "Node {} calibrated: benchmark took {:?} against {:?}, scaling time limits by {:.3}",
+        *NODE, calibration.benchmark_time, calibration.reference_time, calibration.factor
+    );
+    if calibration.outlier {
+        eprintln!(
+            "Node {} is out of {:?} times as slow as the reference, so the benchmark was disturbed or EVAL_STACK_REFERENCE_TIME is wrong",
+            *NODE, FACTOR_RANGE
+        );
+    }
     DB.connect::<Ws>("127.0.0.1:5177").await?;
     DB.signin(Root {
         username: "root",
@@ -35,6 +106,11 @@ pub async fn listen_for_submissions() -> Result<()> {
     .await?;
     DB.use_ns("main").use_db("acm").await?;
 
+    DB.query("UPSERT type::thing('node', $node) SET calibration = $calibration, calibrated_at = time::now()")
+        .bind(("node", NODE.clone()))
+        .bind(("calibration", calibration))
+        .await?;
+
     println!("Listening for submissions...");
     let mut stream = DB
         .query(LIVE_QUERY)
@@ -42,7 +118,13 @@ pub async fn listen_for_submissions() -> Result<()> {
         .stream::<Notification<Submission>>(0)?;
 
     while let Some(submission) = stream.next().await {
-        tokio::spawn(handle_submission(submission));
+        // Leave further submissions queued until a slot is free
+        let permit = SCHEDULER.admit().await;
+        tokio::spawn(async move {
+            let result = handle_submission(submission).await;
+            drop(permit);
+            result
+        });
     }
 
     Ok(())
@@ -64,6 +146,85 @@ pub async fn handle_submission(
         .bind(("status", Status::Judging))
         .await?;
 
+    let id = submission.id.clone();
+    let results = judge(submission, judge_options()).await?;
+
+    let mut result = JudgeResult::default();
+    for res in &results {
+        result.memory_used = result.memory_used.max(res.memory_used);
+        result.time_used = result.time_used.max(res.time_used);
+        result.speed_factor = res.speed_factor;
+        if !matches!(res.status, JudgeStatus::Accepted) {
+            result = res.clone();
+            break;
+        };
+    }
+    result.score = results.score;
+
+    DB.query(
+        "UPDATE $submission SET status = $status, judge_details = $results, judge_result = $result, score = $score",
+    )
+    .bind(("submission", id))
+    .bind(("status", Status::Ready))
+    .bind(("score", results.score))
+    .bind(("results", results.results))
+    .bind(("result", result))
+    .await?;
+
+    Ok(())
+}
+
+/// Re-run a flagged submission with its system calls traced, storing the
+/// results, with the last `syscalls` calls of each run, as `trace_details`.
+pub async fn trace_submission(id: Thing, syscalls: usize) -> Result<TestResults> {
+    let submission: Option<Submission> = DB
+        .query(SUBMISSION_QUERY)
+        .bind(("submission", id.clone()))
+        .await?
+        .take(0)?;
+    let Some(submission) = submission else {
+        anyhow::bail!("Submission {} not found", id);
+    };
+
+    let options = judge_options().trace_syscalls(syscalls);
+    let results = judge(submission, options).await?;
+
+    DB.query("UPDATE $submission SET trace_details = $results")
+        .bind(("submission", id))
+        .bind(("results", results.results.clone()))
+        .await?;
+
+    Ok(results)
+}
+
+fn judge_options() -> JudgeOptions {
+    submission_options(SCHEDULER.clone(), CALIBRATION.factor)
+}
+
+/// Options submissions are judged with, queued on `scheduler` and scaled by
+/// the `speed_factor` of the node: in the namespace sandbox, without network.
+pub fn submission_options(scheduler: Arc<CoreScheduler>, speed_factor: f64) -> JudgeOptions {
+    JudgeOptions {
+        time_limit: Duration::from_secs(1),
+        memory_limit: 128 * 1024 * 1024,
+        fail_fast: true,
+        no_startup_limits: false,
+        unsafe_mode: false,
+        isolate_network: true,
+        sandbox: SandboxMode::Namespaces,
+        scheduler: Some(scheduler),
+        speed_factor: Some(speed_factor),
+        ..Default::default()
+    }
+}
+
+/// Write the code of `submission` to its workspace and run its test cases.
+async fn judge(submission: Submission, mut options: JudgeOptions) -> Result<TestResults> {
+    options.checker = submission.checker;
+    options.interactor = submission.interactor;
+    if let Some(comparator) = submission.comparator {
+        options.comparator = comparator;
+    }
     let base_path = std::env::current_dir().unwrap();
     let workspace = base_path
         .join("workspaces")
@@ -84,44 +245,18 @@ pub async fn handle_submission(
     let mut file = File::create(&source_file_path).await?;
     file.write_all(submission.code.as_bytes()).await?;
 
-    let results = run_test_cases(
+    run_test_cases(
         submission.lang,
         workspace,
         source_file_path,
-        JudgeOptions {
-            time_limit: Duration::from_secs(1),
-            memory_limit: 128 * 1024 * 1024,
-            fail_fast: true,
-            no_startup_limits: false,
-            unsafe_mode: true,
-        },
+        options,
+        SANDBOX.as_ref(),
         submission
             .test_cases
             .into_iter()
-            .map(|tc| (tc.input, tc.output))
+            .map(|tc| TestCase::new(tc.input, tc.output).weight(tc.weight))
             .collect(),
         true,
     )
-    .await?;
-
-    let mut result = JudgeResult::default();
-    for res in &results {
-        result.memory_used = result.memory_used.max(res.memory_used);
-        result.time_used = result.time_used.max(res.time_used);
-        if !matches!(res.status, JudgeStatus::Accepted) {
-            result = res.clone();
-            break;
-        };
-    }
-
-    DB.query(
-        "UPDATE $submission SET status = $status, judge_details = $results, judge_result = $result",
-    )
-    .bind(("submission", submission.id.clone()))
-    .bind(("status", Status::Ready))
-    .bind(("results", results))
-    .bind(("result", result))
-    .await?;
-
-    Ok(())
+    .await
 }
@@ -1,40 +1,24 @@
-use std::{
-    fs,
-    os::unix::process::CommandExt,
-    path::PathBuf,
-    process::{Command, Stdio},
-    time::Duration,
-};
+use std::{fs, io, os::fd::OwnedFd, path::PathBuf, time::Duration};
 
 use anyhow::Result;
-use seccompiler::{
-    BpfProgram, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter, SeccompRule,
-};
+use tokio::time::MissedTickBehavior;
 
 use crate::{
+    compare::Comparison,
     config::{JudgeOptions, TestCase},
-    judge::{Judge, JudgeResult},
+    interactor::CompiledInteractor,
+    judge::{Judge, JudgeResult, JudgeStatus},
+    sandbox::{RunSpec, Sandbox, Spawned},
+    scheduler::CoreSlot,
+    setup::SetupError,
+    utils::{kill_process_group_and_wait, pidfd_open, CappedPipe},
 };
 
-pub fn seccomp_filter() -> anyhow::Result<BpfProgram> {
-    Ok(SeccompFilter::new(
-        vec![(
-            libc::SYS_write,
-            vec![SeccompRule::new(vec![
-                SeccompCondition::new(0, SeccompCmpArgLen::Dword, SeccompCmpOp::Ne, 1)?,
-                SeccompCondition::new(0, SeccompCmpArgLen::Dword, SeccompCmpOp::Ne, 2)?,
-            ])?],
-        )]
-        .into_iter()
-        .collect(),
-        seccompiler::SeccompAction::Allow,
-        seccompiler::SeccompAction::KillProcess,
-        seccompiler::TargetArch::x86_64,
-    )?
-    .try_into()?)
-}
+/// Interval between two samples of memory usage and deadline checks.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
 
 pub async fn execute<'a, B, E, I, O>(
+    sandbox: &dyn Sandbox,
     base: B,
     exec_path: E,
     args: Option<&'a [&'a str]>,
@@ -52,105 +36,214 @@ where
     let input_file = case.input_file.into();
     let output_file = output_file.into();
     let expected_output_file = case.expected_output_file.into();
+    // A checker decides on the output once the run is over
+    let comparator = match options.checker {
+        Some(_) => None,
+        None => Some(
+            case.comparator
+                .unwrap_or_else(|| options.comparator.clone()),
+        ),
+    };
+    // Limits written for the reference host, scaled to this one
+    let options = &options.scaled();
 
-    let mut command = Command::new(exec_path.as_ref());
-    if let Some(args) = args {
-        command.args(args);
-    }
-    command
-        .env_clear()
-        .current_dir(base_path)
-        .stdin(Stdio::from(fs::File::open(&input_file)?))
-        .stdout(Stdio::from(fs::File::create(&output_file)?))
-        .stderr(Stdio::piped());
-
-    let no_sys_as_limits = options.no_startup_limits;
-    let memory_limit = options.memory_limit;
-    let time_limit = options.time_limit.as_secs();
-    if !options.unsafe_mode {
-        unsafe {
-            command.pre_exec(move || {
-                use libc::{rlimit, setrlimit};
-                // Close all file descriptors except for stdin, stdout, and stderr
-                for fd in 3..1024 {
-                    libc::close(fd);
-                }
-                // Prevent child from gaining new privileges
-                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
-                    panic!(
-                        "Failed to disable grant of additional privileges: {}",
-                        std::io::Error::last_os_error()
-                    )
-                }
-                // Unshare the mount namespace to prevent child from gaining new mounts
-                if libc::unshare(libc::CLONE_NEWNS) != 0 {
-                    panic!(
-                        "Failed to unshare namespace: {}",
-                        std::io::Error::last_os_error()
-                    )
-                }
-                // Set memory limit
-                if !no_sys_as_limits {
-                    let limit = rlimit {
-                        rlim_cur: memory_limit,
-                        rlim_max: memory_limit,
-                    };
-                    if setrlimit(libc::RLIMIT_AS, &limit) != 0 {
-                        panic!(
-                            "Failed to set memory limit: {}",
-                            std::io::Error::last_os_error()
-                        )
-                    }
-                    let filter = seccomp_filter().unwrap();
-                    seccompiler::apply_filter(&filter).unwrap();
-                }
-                // Set process limit
-                let proc_limit = rlimit {
-                    rlim_cur: 0,
-                    rlim_max: 0,
-                };
-                if setrlimit(libc::RLIMIT_NPROC, &proc_limit) != 0 {
-                    return Err(std::io::Error::last_os_error());
-                }
-                // Set CPU time limit
-                let cpu_limit = rlimit {
-                    rlim_cur: time_limit,
-                    rlim_max: time_limit,
-                };
-                if setrlimit(libc::RLIMIT_CPU, &cpu_limit) != 0 {
-                    return Err(std::io::Error::last_os_error());
-                }
-                // Disable core dumps
-                if setrlimit(
-                    libc::RLIMIT_CORE,
-                    &rlimit {
-                        rlim_cur: 0,
-                        rlim_max: 0,
-                    },
-                ) != 0
-                {
-                    return Err(std::io::Error::last_os_error());
-                }
-                Ok(())
-            })
-        };
+    // Held until the run is over, so that a pooled uid is not handed out twice,
+    // queueing for one while the pool is exhausted
+    let lease = match &options.run_as {
+        Some(run_as) => Some(run_as.acquire().await?),
+        None => None,
+    };
+    // Queue for an exclusive core, so that concurrent runs do not skew timing
+    let slot = match &options.scheduler {
+        Some(scheduler) => Some(scheduler.acquire().await),
+        None => None,
     };
+    let instant = tokio::time::Instant::now();
+    let spawned = match sandbox.spawn(RunSpec {
+        workspace: &base_path,
+        exec_path: exec_path.as_ref(),
+        args: args.unwrap_or_default(),
+        options,
+        credentials: lease.as_ref().map(|lease| lease.credentials),
+        core: slot.as_ref().map(CoreSlot::core),
+        stdin: fs::File::open(&input_file)?,
+        stdout: fs::File::create(&output_file)?,
+    }) {
+        Ok(spawned) => spawned,
+        Err(e) => return setup_failure(e),
+    };
+
+    supervise(
+        spawned,
+        options,
+        instant,
+        Some(output_file),
+        expected_output_file,
+        comparator,
+    )?
+    .await
+}
 
+/// Run a submission against the `interactor`, the standard output of each
+/// being piped to the standard input of the other.
+///
+/// Both runs are limited and their memory tracked, the interactor's with
+/// the trusted options of problem setters' programs. The verdict combines
+/// the interactor's exit code with the submission's status.
+pub async fn execute_interactive<'a, B, E>(
+    sandbox: &dyn Sandbox,
+    base: B,
+    exec_path: E,
+    args: Option<&'a [&'a str]>,
+    options: &'a JudgeOptions,
+    case: TestCase<PathBuf, PathBuf>,
+    interactor: &CompiledInteractor,
+) -> Result<JudgeResult>
+where
+    B: Into<PathBuf>,
+    E: AsRef<str>,
+{
+    let base_path = base.into();
+    let options = &options.scaled();
+    let interactor_options = interactor.options(options);
+    let interactor_args = interactor.prepare(&case)?;
+    let interactor_args: Vec<&str> = interactor_args.iter().map(String::as_str).collect();
+
+    let lease = match &options.run_as {
+        Some(run_as) => Some(run_as.acquire().await?),
+        None => None,
+    };
+    let slot = match &options.scheduler {
+        Some(scheduler) => Some(scheduler.acquire().await),
+        None => None,
+    };
+    let (submission_stdin, interactor_stdout) = pipe()?;
+    let (interactor_stdin, submission_stdout) = pipe()?;
     let instant = tokio::time::Instant::now();
-    let child = command.spawn()?;
+    // Each end is closed here once spawned, so that either sees the end of
+    // its input when the other exits
+    let mut submission = match sandbox.spawn(RunSpec {
+        workspace: &base_path,
+        exec_path: exec_path.as_ref(),
+        args: args.unwrap_or_default(),
+        options,
+        credentials: lease.as_ref().map(|lease| lease.credentials),
+        core: slot.as_ref().map(CoreSlot::core),
+        stdin: submission_stdin,
+        stdout: submission_stdout,
+    }) {
+        Ok(submission) => submission,
+        Err(e) => return setup_failure(e),
+    };
+    let spawned = sandbox.spawn(RunSpec {
+        workspace: &interactor.workspace,
+        exec_path: &interactor.exec_path.to_string_lossy(),
+        args: &interactor_args,
+        options: &interactor_options,
+        // As the judge, out of reach of the submission's user, which could
+        // otherwise signal or trace it
+        credentials: None,
+        core: None,
+        stdin: interactor_stdin,
+        stdout: interactor_stdout,
+    });
+    let spawned = match spawned {
+        Ok(spawned) => spawned,
+        Err(e) => {
+            // Nothing else would stop the submission
+            kill_process_group_and_wait(submission.child.id()).await;
+            let _ = submission.child.try_wait();
+            return setup_failure(e);
+        }
+    };
 
-    let id = child.id();
+    let submission = supervise(
+        submission,
+        options,
+        instant,
+        None,
+        case.expected_output_file.clone(),
+        None,
+    )?;
+    let interaction = supervise(
+        spawned,
+        &interactor_options,
+        instant,
+        None,
+        case.expected_output_file,
+        None,
+    )?;
+    let (submission, interaction) = tokio::join!(submission, interaction);
+    Ok(interactor.verdict(submission?, interaction?))
+}
+
+/// The result of a run the sandbox failed to spawn, a system error when it
+/// failed to set up the run, and `error` itself otherwise.
+fn setup_failure(error: anyhow::Error) -> Result<JudgeResult> {
+    let error = error.downcast::<SetupError>()?;
+    Ok(JudgeResult {
+        status: JudgeStatus::SystemError {
+            message: error.to_string(),
+        },
+        ..Default::default()
+    })
+}
 
-    Judge {
-        child,
+/// Judge a `spawned` run, comparing what it wrote to `stdout_file` with
+/// `comparator` if there is one.
+fn supervise(
+    mut spawned: Spawned,
+    options: &JudgeOptions,
+    instant: tokio::time::Instant,
+    stdout_file: Option<PathBuf>,
+    expected_output_file: PathBuf,
+    comparator: Option<Comparison>,
+) -> Result<Judge> {
+    let id = spawned.child.id();
+    let stderr = CappedPipe::new(spawned.child.stderr.take().unwrap(), options.stderr_limit)?;
+    let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    Ok(Judge {
+        child: spawned.child,
         id,
         time_limit: options.time_limit,
+        time_limit_mode: options.time_limit_mode,
+        wall_time_limit: options.wall_time_limit(),
         memory_limit: options.memory_limit,
+        output_limit: options.limits.clone().unwrap_or_default().file_size,
         instant,
         memory_used: 0,
         time_used: Duration::from_secs(0),
-        stdout_file: output_file,
+        cpu_time: Duration::from_secs(0),
+        idle_time_limit: options.idle_time_limit,
+        last_progress: Duration::from_secs(0),
+        rusage: None,
+        pidfd: pidfd_open(id),
+        interval,
+        stdout_file,
+        stderr,
         expected_output_file,
-    }
-    .await
+        cgroup: spawned.cgroup,
+        seccomp: spawned.seccomp,
+        setup_errors: spawned.setup_errors,
+        signal_exit_codes: spawned.wrapped,
+        wrapper_report: spawned.wrapper_report,
+        trace: spawned.trace,
+        instruction_limit: options.instruction_limit,
+        instruction_counter: spawned.instructions,
+        instructions: None,
+        speed_factor: options.speed_factor,
+        comparator,
+        leftover: None,
+    })
+}
+
+/// A pipe as files, both ends closed on `exec`.
+fn pipe() -> io::Result<(fs::File, fs::File)> {
+    let (reader, writer) = io::pipe()?;
+    Ok((
+        fs::File::from(OwnedFd::from(reader)),
+        fs::File::from(OwnedFd::from(writer)),
+    ))
 }
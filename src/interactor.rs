@@ -0,0 +1,142 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+
+use crate::{
+    checker::{compile_program, trusted_options, Checked, PC_BASE_EXIT_CODE},
+    compile::Language,
+    config::{JudgeOptions, TestCase},
+    judge::{JudgeResult, JudgeStatus},
+};
+
+/// Time an interactor may run past the submission's wall-clock limit, to
+/// notice it is gone and report.
+const INTERACTOR_GRACE: Duration = Duration::from_secs(1);
+
+/// A program talking to the submission over its standard streams, for
+/// interactive problems.
+///
+/// Follows the conventions of testlib: the interactor runs in the sandbox as
+/// `interactor <input> <output> <answer> <result>`, with its standard output
+/// piped to the submission's standard input and the other way around. It
+/// exits like a [`Checker`](crate::checker::Checker), writing its message to
+/// the result file and anything a checker should check to the output file.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Interactor {
+    /// A compiled language, usually C++ with `testlib.h`.
+    pub language: Language,
+    pub source_file: PathBuf,
+    /// Exit code of `_pc(0)`, as [`Checker::partial_base`](crate::checker::Checker::partial_base).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub partial_base: i32,
+}
+
+/// An interactor compiled into a workspace of its own.
+#[derive(Debug, Clone)]
+pub struct CompiledInteractor {
+    pub language: Language,
+    pub exec_path: PathBuf,
+    pub workspace: PathBuf,
+    pub partial_base: i32,
+}
+
+impl Interactor {
+    pub fn new<S: Into<PathBuf>>(language: Language, source_file: S) -> Self {
+        Self {
+            language,
+            source_file: source_file.into(),
+            partial_base: PC_BASE_EXIT_CODE,
+        }
+    }
+
+    pub fn partial_base(mut self, partial_base: i32) -> Self {
+        self.partial_base = partial_base;
+        self
+    }
+
+    /// Compile the interactor into `workspace`, which must be out of the
+    /// submission's workspace, where a submission could replace it.
+    pub async fn compile(&self, workspace: &Path) -> Result<CompiledInteractor> {
+        let exec_path = workspace.join("interactor");
+        compile_program(self.language, &self.source_file, &exec_path, "interactor").await?;
+        Ok(CompiledInteractor {
+            language: self.language,
+            exec_path,
+            workspace: workspace.to_path_buf(),
+            partial_base: self.partial_base,
+        })
+    }
+}
+
+impl CompiledInteractor {
+    /// Where the interactor writes what a checker should check.
+    pub fn output_file(&self) -> PathBuf {
+        self.workspace.join("output")
+    }
+
+    pub(crate) fn result_file(&self) -> PathBuf {
+        self.workspace.join("result")
+    }
+
+    /// Options of the interactor for a submission run with `options`.
+    pub(crate) fn options(&self, options: &JudgeOptions) -> JudgeOptions {
+        let time_limit = options.wall_time_limit() + INTERACTOR_GRACE;
+        JudgeOptions {
+            // Waiting for a core of its own would stall the submission
+            scheduler: None,
+            ..trusted_options(self.language, options, time_limit)
+        }
+    }
+
+    /// Copy the files of `test_case` into the workspace, returning the
+    /// arguments of the interactor.
+    pub(crate) fn prepare(&self, test_case: &TestCase<PathBuf, PathBuf>) -> Result<Vec<String>> {
+        // Files out of the workspace may be out of the sandbox's sight
+        let input = self.workspace.join("input");
+        let answer = self.workspace.join("answer");
+        fs::copy(&test_case.input_file, &input)?;
+        fs::copy(&test_case.expected_output_file, &answer)?;
+        let _ = fs::remove_file(self.output_file());
+        let _ = fs::remove_file(self.result_file());
+        let _ = fs::remove_file(self.workspace.join("score"));
+        Ok([input, self.output_file(), answer, self.result_file()]
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    /// The verdict of an interaction, from the runs of the submission and of
+    /// the interactor.
+    ///
+    /// The interactor rejecting the submission wins over the submission
+    /// failing, as it may have crashed on a wrong reply, unless the submission
+    /// exceeded a limit: killing it ends the interaction, which the
+    /// interactor then rejects. Otherwise a failed submission keeps its
+    /// verdict, and an interactor failing is a system error.
+    pub(crate) fn verdict(
+        &self,
+        mut submission: JudgeResult,
+        interactor: JudgeResult,
+    ) -> JudgeResult {
+        let checked = match interactor.status {
+            // Killed writing to a submission no longer reading its input
+            JudgeStatus::RuntimeError { code, .. } if code == 128 + libc::SIGPIPE => Checked {
+                status: JudgeStatus::WrongAnswer { mismatch: None },
+                message: Some("The submission stopped reading its input".to_string()),
+            },
+            _ => Checked::from_run(interactor, &self.workspace, "Interactor", self.partial_base),
+        };
+        let limit_exceeded = submission.status.is_limit_exceeded();
+        if submission.is_accepted() || (checked.is_rejected() && !limit_exceeded) {
+            submission.status = checked.status;
+        }
+        submission.checker_message = checked.message;
+        submission
+    }
+}
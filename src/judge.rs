@@ -1,25 +1,59 @@
 use std::{
     fs,
     future::Future,
-    io::{BufRead, BufReader, Read},
-    os::unix::process::ExitStatusExt,
+    os::{fd::OwnedFd, unix::process::ExitStatusExt},
     path::PathBuf,
-    task::Poll,
+    pin::Pin,
+    process::ExitStatus,
+    task::{Context, Poll},
     time::Duration,
 };
 
 use anyhow::Result;
+use tokio::{
+    io::unix::AsyncFd,
+    time::{Interval, Sleep},
+};
 
-use crate::utils::get_memory_usage;
+use crate::{
+    cgroup::Cgroup,
+    compare::{Comparator, Comparison, Mismatch, Verdict},
+    config::TimeLimitMode,
+    perf::{InstructionCount, InstructionCounter},
+    sandbox::WrapperReport,
+    seccomp::ViolationListener,
+    setup::SetupErrors,
+    trace::{SyscallTrace, TracedSyscall},
+    utils::{
+        get_cpu_time, get_memory_usage, kill_process_group, process_tree, wait4, CappedPipe,
+        GROUP_EXIT_INTERVAL, GROUP_EXIT_TIMEOUT,
+    },
+};
 
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum JudgeStatus {
     Accepted,
-    WrongAnswer,
+    WrongAnswer {
+        /// First difference from the expected output, if the comparator tells.
+        mismatch: Option<Mismatch>,
+    },
+    /// The answer is worth part of the test's score, as reported by a checker.
+    PartiallyAccepted {
+        /// Fraction of the test's score, between 0 and 1.
+        points: f64,
+    },
+    /// The answer is right but laid out wrongly, as reported by a checker or
+    /// by [`Comparison::Presentation`].
+    PresentationError,
     TimeLimitExceeded,
+    /// The CPU time stopped advancing for longer than the idle time limit,
+    /// e.g. while blocked on input that never comes.
+    IdlenessLimitExceeded,
     MemoryLimitExceeded,
+    /// The output exceeded the output limit.
+    OutputLimitExceeded,
     RuntimeError {
         code: i32,
         stderr: String,
@@ -27,15 +61,27 @@ pub enum JudgeStatus {
     CompileError {
         message: String,
     },
+    /// A fault of the judge itself, not caused by the submission.
     SystemError {
-        code: i32,
-        stderr: String,
-        signal: i32,
+        message: String,
     },
     SegmentFault {
         code: i32,
         stderr: String,
     },
+    /// The submission made a system call forbidden by its seccomp policy.
+    RestrictedFunction {
+        /// Number of the offending system call, if it could be determined.
+        syscall: Option<i64>,
+    },
+    /// Killed by `SIGFPE`, e.g. on an integer division by zero.
+    FloatingPointError {
+        stderr: String,
+    },
+    /// Killed by `SIGABRT`, e.g. on a failed assertion.
+    Aborted {
+        stderr: String,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -45,6 +91,42 @@ pub struct JudgeResult {
     pub status: JudgeStatus,
     pub time_used: Duration,
     pub memory_used: u64,
+    /// Total CPU time consumed by the run.
+    pub cpu_time: Duration,
+    /// User CPU time reported by `wait4`.
+    pub user_time: Duration,
+    /// System CPU time reported by `wait4`.
+    pub system_time: Duration,
+    /// Maximum resident set size in bytes reported by `wait4`.
+    pub max_rss: u64,
+    /// Last system calls of a traced run, oldest first.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub trace: Vec<TracedSyscall>,
+    /// Instructions retired by the run, when counted.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub instructions: Option<InstructionCount>,
+    /// Factor the time limits of the run were scaled by for this host.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub speed_factor: Option<f64>,
+    /// Message of the checker or interactor which decided on the run.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub checker_message: Option<String>,
+    /// Score of the test, the fraction it earned times its weight, set by
+    /// `run_test_cases`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub score: f64,
 }
 
 impl Default for JudgeResult {
@@ -53,6 +135,15 @@ impl Default for JudgeResult {
             status: JudgeStatus::Accepted,
             time_used: Duration::from_secs(0),
             memory_used: 0,
+            cpu_time: Duration::from_secs(0),
+            user_time: Duration::from_secs(0),
+            system_time: Duration::from_secs(0),
+            max_rss: 0,
+            trace: vec![],
+            instructions: None,
+            speed_factor: None,
+            checker_message: None,
+            score: 0.0,
         }
     }
 }
@@ -67,139 +158,403 @@ impl JudgeResult {
     }
 }
 
+impl JudgeStatus {
+    /// Fraction of a test's score the verdict earns.
+    pub fn points(&self) -> f64 {
+        match self {
+            JudgeStatus::Accepted => 1.0,
+            JudgeStatus::PartiallyAccepted { points } => *points,
+            _ => 0.0,
+        }
+    }
+
+    /// Whether the run was stopped for exceeding one of its limits.
+    pub fn is_limit_exceeded(&self) -> bool {
+        matches!(
+            self,
+            JudgeStatus::TimeLimitExceeded
+                | JudgeStatus::IdlenessLimitExceeded
+                | JudgeStatus::MemoryLimitExceeded
+                | JudgeStatus::OutputLimitExceeded
+        )
+    }
+}
+
 pub struct Judge {
     pub child: std::process::Child,
     pub id: u32,
     pub time_limit: Duration,
+    pub time_limit_mode: TimeLimitMode,
+    pub wall_time_limit: Duration,
     pub memory_limit: u64,
+    pub output_limit: u64,
     pub instant: tokio::time::Instant,
     pub memory_used: u64,
     pub time_used: Duration,
-    pub stdout_file: PathBuf,
+    pub cpu_time: Duration,
+    /// Window after which a run whose CPU time does not advance is stopped.
+    pub idle_time_limit: Option<Duration>,
+    /// Wall-clock time at which the CPU time last advanced.
+    pub last_progress: Duration,
+    pub rusage: Option<libc::rusage>,
+    /// Becomes readable when the child exits, if `pidfd_open` is supported.
+    pub pidfd: Option<AsyncFd<OwnedFd>>,
+    /// Ticks at which memory usage and deadlines are checked.
+    pub interval: Interval,
+    /// `None` when standard output is a pipe, e.g. to an interactor.
+    pub stdout_file: Option<PathBuf>,
+    pub stderr: CappedPipe,
     pub expected_output_file: PathBuf,
+    pub cgroup: Option<Cgroup>,
+    /// Reports system calls rejected by the seccomp allowlist.
+    pub seccomp: Option<ViolationListener>,
+    /// Failures of the sandbox setup, reported by the child before `exec`.
+    pub setup_errors: SetupErrors,
+    /// Exit codes above 128 stand for the signal which killed the submission,
+    /// as reported by wrapping sandboxes.
+    pub signal_exit_codes: bool,
+    /// Tells failures of a wrapping sandbox from the submission's.
+    pub wrapper_report: Option<WrapperReport>,
+    /// System calls of the submission, when traced.
+    pub trace: Option<SyscallTrace>,
+    /// Instruction budget, for [`TimeLimitMode::Instructions`].
+    pub instruction_limit: Option<u64>,
+    pub instruction_counter: Option<InstructionCounter>,
+    /// Last count read from `instruction_counter`.
+    pub instructions: Option<InstructionCount>,
+    /// Factor the time limits were scaled by.
+    pub speed_factor: Option<f64>,
+    /// `None` when the output is left to a checker or an interactor,
+    /// accepting every run which exits successfully.
+    pub comparator: Option<Comparison>,
+    /// Result of a run over, held until the rest of its process group is gone.
+    pub(crate) leftover: Option<Leftover>,
 }
 
-impl Future for Judge {
-    type Output = Result<JudgeResult>;
+/// A result waiting for the killed processes of its run to exit.
+pub(crate) struct Leftover {
+    result: JudgeResult,
+    /// Whether the child was waited for, its resource usage being in the result.
+    reaped: bool,
+    deadline: tokio::time::Instant,
+    retry: Pin<Box<Sleep>>,
+}
 
-    fn poll(
-        mut self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Self::Output> {
-        match self.child.try_wait()? {
-            Some(status) => {
-                self.time_used = self.instant.elapsed();
-                drop(self.child.stdin.take());
-                drop(self.child.stdout.take());
-                if status.success() {
-                    let stdout = BufReader::new(fs::File::open(&self.stdout_file)?);
-                    let expected_out = BufReader::new(fs::File::open(&self.expected_output_file)?);
-
-                    let mut stdout_lines = stdout.lines();
-                    let mut expected_out_lines = expected_out.lines();
-
-                    let matched = loop {
-                        match (stdout_lines.next(), expected_out_lines.next()) {
-                            (None, None) => break true,
-                            (Some(output), None) => {
-                                if output?
-                                    .trim_end_matches(|c: char| c.is_whitespace() || c == '\n')
-                                    != ""
-                                {
-                                    break false;
-                                }
-                            }
-                            (None, Some(expected_output)) => {
-                                if expected_output?
-                                    .trim_end_matches(|c: char| c.is_whitespace() || c == '\n')
-                                    != ""
-                                {
-                                    break false;
-                                }
-                            }
-                            (Some(output), Some(expected_output)) => {
-                                if output?
-                                    .trim_end_matches(|c: char| c.is_whitespace() || c == '\n')
-                                    != expected_output?
-                                        .trim_end_matches(|c: char| c.is_whitespace() || c == '\n')
-                                {
-                                    break false;
-                                }
-                            }
-                        }
-                    };
-
-                    if matched {
-                        Poll::Ready(Ok(JudgeResult {
-                            status: JudgeStatus::Accepted,
-                            time_used: self.time_used,
-                            memory_used: self.memory_used,
-                        }))
-                    } else {
-                        Poll::Ready(Ok(JudgeResult {
-                            status: JudgeStatus::WrongAnswer,
-                            time_used: self.time_used,
-                            memory_used: self.memory_used,
-                        }))
-                    }
-                } else {
-                    let mut stderr = String::new();
-                    let _ = self
-                        .child
-                        .stderr
-                        .take()
-                        .unwrap()
-                        .read_to_string(&mut stderr);
-                    let code = status.code().unwrap_or(-1);
-                    match status.signal() {
-                        Some(libc::SIGSEGV) | Some(libc::SIGBUS) | Some(libc::SIGILL) => {
-                            Poll::Ready(Ok(JudgeResult {
-                                status: JudgeStatus::SegmentFault { code, stderr },
-                                time_used: self.time_used,
-                                memory_used: self.memory_used,
-                            }))
-                        }
-                        Some(signal) => Poll::Ready(Ok(JudgeResult {
-                            status: JudgeStatus::SystemError {
-                                code,
-                                signal,
-                                stderr,
-                            },
-                            time_used: self.time_used,
-                            memory_used: self.memory_used,
-                        })),
-                        None => Poll::Ready(Ok(JudgeResult {
-                            status: JudgeStatus::RuntimeError { code, stderr },
-                            time_used: self.time_used,
-                            memory_used: self.memory_used,
-                        })),
-                    }
-                }
+impl Judge {
+    fn result(&self, status: JudgeStatus) -> JudgeResult {
+        let (user_time, system_time, max_rss) = match &self.rusage {
+            Some(rusage) => (
+                timeval_to_duration(rusage.ru_utime),
+                timeval_to_duration(rusage.ru_stime),
+                rusage.ru_maxrss as u64 * 1024,
+            ),
+            None => Default::default(),
+        };
+        let cpu_time = match &self.cgroup {
+            Some(cgroup) => cgroup.stats().cpu_usage,
+            None => user_time + system_time,
+        };
+        JudgeResult {
+            status,
+            time_used: self.time_used,
+            memory_used: self.memory_used,
+            cpu_time: cpu_time.max(self.cpu_time),
+            user_time,
+            system_time,
+            max_rss,
+            trace: self
+                .trace
+                .as_ref()
+                .map(SyscallTrace::syscalls)
+                .unwrap_or_default(),
+            instructions: self.instructions,
+            speed_factor: self.speed_factor,
+            checker_message: None,
+            score: 0.0,
+        }
+    }
+
+    /// Kill the whole process tree of the child, which is reaped along with
+    /// every descendant without blocking, see `settle`.
+    fn kill(&mut self) {
+        self.kill_descendants();
+        self.time_used = self.instant.elapsed();
+        if let Some(trace) = &mut self.trace {
+            trace.drain();
+        }
+        self.count_instructions();
+    }
+
+    /// Signal every process spawned by the submission. The child leads its
+    /// own process group, and the cgroup also covers processes that left it.
+    fn kill_descendants(&self) {
+        if let Some(cgroup) = &self.cgroup {
+            cgroup.kill();
+        }
+        unsafe { libc::kill(-(self.id as i32), libc::SIGKILL) };
+    }
+
+    fn time_limit_exceeded(&self) -> bool {
+        match self.time_limit_mode {
+            TimeLimitMode::Wall => self.time_used > self.time_limit,
+            TimeLimitMode::Cpu => self.cpu_time > self.time_limit,
+            TimeLimitMode::Instructions => self.instructions_exceeded(),
+        }
+    }
+
+    fn instructions_exceeded(&self) -> bool {
+        match (self.instructions, self.instruction_limit) {
+            (Some(instructions), Some(limit)) => instructions.count > limit,
+            _ => false,
+        }
+    }
+
+    /// Read the instructions retired so far, keeping the last count once the
+    /// counter is gone.
+    fn count_instructions(&mut self) {
+        if let Some(counter) = &mut self.instruction_counter {
+            if let Some(instructions) = counter.read() {
+                self.instructions = Some(instructions);
             }
+        }
+    }
+
+    /// Whether a `SIGKILL` may come from a limit rather than the submission,
+    /// e.g. from the hard `RLIMIT_CPU`.
+    fn deadline_reached(&self) -> bool {
+        let time_limit_reached = match self.time_limit_mode {
+            TimeLimitMode::Instructions => self.instructions_exceeded(),
+            TimeLimitMode::Wall | TimeLimitMode::Cpu => self.cpu_time >= self.time_limit,
+        };
+        time_limit_reached || self.time_used >= self.wall_time_limit
+    }
+
+    /// Sample the resource usage of the running child and kill it on limit breach.
+    fn sample(&mut self) -> Result<Option<JudgeResult>> {
+        let (memory_used, cpu_time) = match &self.cgroup {
+            Some(cgroup) => (cgroup.memory_usage(), Some(cgroup.stats().cpu_usage)),
             None => {
-                if let Some(memory_used) = get_memory_usage(self.id) {
-                    self.memory_used = memory_used.max(self.memory_used);
-                };
-                if self.memory_used > self.memory_limit {
-                    self.child.kill()?;
-                    self.time_used = self.instant.elapsed();
-                    return Poll::Ready(Ok(JudgeResult {
-                        status: JudgeStatus::MemoryLimitExceeded,
-                        time_used: self.time_used,
-                        memory_used: self.memory_used,
-                    }));
+                let tree = process_tree(self.id);
+                (
+                    tree.iter().map(|pid| get_memory_usage(*pid)).sum(),
+                    tree.iter().map(|pid| get_cpu_time(*pid)).sum(),
+                )
+            }
+        };
+        if let Some(memory_used) = memory_used {
+            self.memory_used = memory_used.max(self.memory_used);
+        };
+        self.time_used = self.instant.elapsed();
+        if let Some(cpu_time) = cpu_time {
+            if cpu_time > self.cpu_time {
+                self.last_progress = self.time_used;
+            }
+            self.cpu_time = cpu_time.max(self.cpu_time);
+        }
+        self.count_instructions();
+        if self.memory_used > self.memory_limit {
+            self.kill();
+            return Ok(Some(self.result(JudgeStatus::MemoryLimitExceeded)));
+        }
+        if self.time_limit_exceeded() || self.time_used > self.wall_time_limit {
+            self.kill();
+            return Ok(Some(self.result(JudgeStatus::TimeLimitExceeded)));
+        }
+        if let Some(idle_time_limit) = self.idle_time_limit {
+            if self.time_used - self.last_progress > idle_time_limit {
+                self.kill();
+                return Ok(Some(self.result(JudgeStatus::IdlenessLimitExceeded)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Build the result of a child that has exited on its own.
+    fn finish(&mut self, status: ExitStatus, rusage: libc::rusage) -> Result<JudgeResult> {
+        self.time_used = self.instant.elapsed();
+        self.rusage = Some(rusage);
+        // Leftover descendants must not outlive the run, see `settle`
+        self.kill_descendants();
+        drop(self.child.stdin.take());
+        drop(self.child.stdout.take());
+        if let Some(trace) = &mut self.trace {
+            trace.drain();
+        }
+        self.count_instructions();
+        if let Some(error) = self.setup_errors.read() {
+            return Ok(self.result(JudgeStatus::SystemError {
+                message: error.to_string(),
+            }));
+        }
+        if let Some(report) = &self.wrapper_report {
+            self.stderr.drain();
+            if let Some(message) = report.failure(status, &self.stderr.contents()) {
+                return Ok(self.result(JudgeStatus::SystemError { message }));
+            }
+        }
+        if let Some(stats) = self.cgroup.as_ref().map(Cgroup::stats) {
+            self.memory_used = stats.memory_peak.max(self.memory_used);
+            if stats.oom_kills > 0 {
+                return Ok(self.result(JudgeStatus::MemoryLimitExceeded));
+            }
+        }
+        self.cpu_time = self.result(JudgeStatus::Accepted).cpu_time;
+        if self.time_limit_exceeded() || self.time_used > self.wall_time_limit {
+            return Ok(self.result(JudgeStatus::TimeLimitExceeded));
+        }
+        let signal = status.signal().or_else(|| {
+            let code = status.code().filter(|code| *code > 128);
+            code.filter(|_| self.signal_exit_codes)
+                .map(|code| code - 128)
+        });
+        // Runtimes such as Python ignore `SIGXFSZ` and fail on `EFBIG` instead
+        let output_size = self
+            .stdout_file
+            .as_ref()
+            .and_then(|file| fs::metadata(file).ok())
+            .map_or(0, |m| m.len());
+        if signal == Some(libc::SIGXFSZ) || output_size > self.output_limit {
+            return Ok(self.result(JudgeStatus::OutputLimitExceeded));
+        }
+        if status.success() {
+            let verdict = match (&self.comparator, &self.stdout_file) {
+                (Some(comparator), Some(stdout_file)) => {
+                    comparator.compare(stdout_file, &self.expected_output_file)?
                 }
-                if self.instant.elapsed() > self.time_limit {
-                    self.child.kill()?;
-                    self.time_used = self.instant.elapsed();
-                    return Poll::Ready(Ok(JudgeResult {
-                        status: JudgeStatus::TimeLimitExceeded,
-                        time_used: self.time_used,
-                        memory_used: self.memory_used,
-                    }));
+                _ => Verdict::Accepted,
+            };
+
+            match verdict {
+                Verdict::Accepted => Ok(self.result(JudgeStatus::Accepted)),
+                Verdict::WrongAnswer(mismatch) => {
+                    Ok(self.result(JudgeStatus::WrongAnswer { mismatch }))
+                }
+                Verdict::PresentationError => Ok(self.result(JudgeStatus::PresentationError)),
+            }
+        } else {
+            self.stderr.drain();
+            let stderr = self.stderr.contents();
+            let code = status.code().unwrap_or(-1);
+            let status = match signal {
+                Some(libc::SIGSEGV) | Some(libc::SIGBUS) | Some(libc::SIGILL) => {
+                    JudgeStatus::SegmentFault { code, stderr }
+                }
+                Some(libc::SIGFPE) => JudgeStatus::FloatingPointError { stderr },
+                Some(libc::SIGABRT) => JudgeStatus::Aborted { stderr },
+                // Raised by filters without a listener, e.g. on a foreign architecture,
+                // and reported by the tracer along with the rejected system call
+                Some(libc::SIGSYS) => JudgeStatus::RestrictedFunction {
+                    syscall: self.trace.as_ref().and_then(SyscallTrace::rejected),
+                },
+                Some(libc::SIGXCPU) => JudgeStatus::TimeLimitExceeded,
+                Some(libc::SIGKILL) if self.deadline_reached() => JudgeStatus::TimeLimitExceeded,
+                Some(signal) => JudgeStatus::RuntimeError {
+                    code: 128 + signal,
+                    stderr,
+                },
+                None => JudgeStatus::RuntimeError { code, stderr },
+            };
+            Ok(self.result(status))
+        }
+    }
+
+    /// Hold `result` until the killed process group of the run is gone, so
+    /// that nothing of it outlives the run, and until the child is `reaped`.
+    fn settle(
+        &mut self,
+        result: Result<JudgeResult>,
+        reaped: bool,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<JudgeResult>> {
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => return Poll::Ready(Err(e)),
+        };
+        self.leftover = Some(Leftover {
+            result,
+            reaped,
+            deadline: tokio::time::Instant::now() + GROUP_EXIT_TIMEOUT,
+            retry: Box::pin(tokio::time::sleep(Duration::ZERO)),
+        });
+        self.poll_leftover(cx)
+    }
+
+    /// Check whether the child was reaped and the process group is gone,
+    /// retrying on a timer rather than blocking the runtime, and giving up at
+    /// the deadline.
+    fn poll_leftover(&mut self, cx: &mut Context<'_>) -> Poll<Result<JudgeResult>> {
+        loop {
+            let Some(leftover) = &self.leftover else {
+                unreachable!("polled without a leftover result");
+            };
+            // Before the group, whose sweep would reap the child and lose its usage
+            if !leftover.reaped {
+                if let Some((_, rusage)) = wait4(self.id, false)? {
+                    self.rusage = Some(rusage);
+                    let result = self.result(leftover.result.status.clone());
+                    self.leftover = Some(Leftover {
+                        result,
+                        reaped: true,
+                        ..self.leftover.take().unwrap()
+                    });
+                }
+            }
+            let Some(leftover) = &mut self.leftover else {
+                unreachable!("polled without a leftover result");
+            };
+            let now = tokio::time::Instant::now();
+            if (leftover.reaped && kill_process_group(self.id)) || now >= leftover.deadline {
+                let leftover = self.leftover.take().unwrap();
+                return Poll::Ready(Ok(leftover.result));
+            }
+            leftover.retry.as_mut().reset(now + GROUP_EXIT_INTERVAL);
+            if leftover.retry.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+fn timeval_to_duration(tv: libc::timeval) -> Duration {
+    Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+}
+
+impl Future for Judge {
+    type Output = Result<JudgeResult>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.leftover.is_some() {
+            return self.poll_leftover(cx);
+        }
+        loop {
+            // Register for the exit notification before checking, so that an exit
+            // between the check and returning `Pending` still wakes us up.
+            if let Some(pidfd) = &self.pidfd {
+                let _ = pidfd.poll_read_ready(cx);
+            }
+            self.stderr.poll_drain(cx);
+            if let Some(trace) = &mut self.trace {
+                trace.poll_drain(cx);
+            }
+            if let Some(syscall) = self.seccomp.as_mut().and_then(|s| s.poll_violation(cx)) {
+                self.kill();
+                let result = self.result(JudgeStatus::RestrictedFunction {
+                    syscall: Some(syscall),
+                });
+                return self.settle(Ok(result), false, cx);
+            }
+            if let Some((status, rusage)) = wait4(self.id, false)? {
+                let result = self.finish(status, rusage);
+                return self.settle(result, true, cx);
+            }
+            match self.interval.poll_tick(cx) {
+                Poll::Ready(_) => {
+                    // Only killed runs have a result before exiting
+                    if let Some(result) = self.sample().transpose() {
+                        return self.settle(result, false, cx);
+                    }
                 }
-                cx.waker().wake_by_ref();
-                Poll::Pending
+                Poll::Pending => return Poll::Pending,
             }
         }
     }
@@ -1,8 +1,22 @@
+pub mod calibration;
 pub mod case;
+pub mod cgroup;
+pub mod checker;
+pub mod compare;
 pub mod compile;
 pub mod config;
 pub mod exec;
+pub mod interactor;
 pub mod judge;
+pub mod namespace;
+pub mod perf;
+pub mod sandbox;
+pub mod scheduler;
+pub mod seccomp;
+pub mod setup;
+pub mod subtask;
+pub mod trace;
+pub mod user;
 pub mod utils;
 
 #[cfg(feature = "engine")]
@@ -0,0 +1,409 @@
+use std::{
+    collections::BTreeSet,
+    ffi::CString,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{
+    setup::{check, SetupError, SetupStep},
+    user::Credentials,
+};
+
+/// Host paths bind-mounted read-only into every sandbox, if they exist.
+const SYSTEM_PATHS: &[&str] = &[
+    "/bin",
+    "/sbin",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/libx32",
+    "/usr",
+    "/etc/alternatives",
+    "/etc/ld.so.cache",
+    "/etc/ld.so.conf",
+    "/etc/ld.so.conf.d",
+    "/dev/null",
+    "/dev/zero",
+    "/dev/random",
+    "/dev/urandom",
+];
+
+/// Empty directory on the host used as the mount point of the new root.
+///
+/// A tmpfs is mounted over it inside each private mount namespace, so that
+/// concurrent runs never see each other's roots.
+const ROOT_DIR: &str = "eval-stack-root";
+
+enum Entry {
+    /// Bind-mount a host directory or file at the same path, read-only
+    /// unless `writable`.
+    Bind {
+        source: CString,
+        target: CString,
+        writable: bool,
+    },
+    /// Recreate a host symbolic link, such as `/bin -> usr/bin`.
+    Symlink { link: CString, target: CString },
+}
+
+/// Everything needed to set up the namespace sandbox in a forked child.
+///
+/// All allocations happen in the parent: the child only issues system calls,
+/// which keeps the `pre_exec` hook async-signal-safe.
+pub struct NamespaceSandbox {
+    uid_map: CString,
+    gid_map: CString,
+    root: CString,
+    dirs: Vec<CString>,
+    files: Vec<CString>,
+    entries: Vec<Entry>,
+    proc_dir: CString,
+    workdir: CString,
+}
+
+impl NamespaceSandbox {
+    /// Plan a minimal read-only root containing the system libraries, the
+    /// language runtime at `exec_path` and the `workspace`, which is only
+    /// writable with `writable_workspace`.
+    ///
+    /// With `credentials`, the caller must switch to them before [`Self::enter`]:
+    /// a user namespace can only map the user that creates it.
+    pub fn new<W: AsRef<Path>, E: AsRef<Path>>(
+        workspace: W,
+        exec_path: E,
+        credentials: Option<Credentials>,
+        writable_workspace: bool,
+    ) -> Result<Self> {
+        let root = std::env::temp_dir().join(ROOT_DIR);
+        std::fs::create_dir_all(&root)?;
+
+        let workspace = workspace.as_ref().canonicalize()?;
+        let sources = visible_paths(&workspace, exec_path.as_ref());
+
+        let mut dirs = BTreeSet::new();
+        let mut files = vec![];
+        let mut entries = vec![];
+        for source in sources {
+            let target = root.join(source.strip_prefix("/")?);
+            for ancestor in target.ancestors().skip(1) {
+                if ancestor == root {
+                    break;
+                }
+                dirs.insert(ancestor.to_path_buf());
+            }
+            let metadata = source.symlink_metadata()?;
+            if metadata.is_symlink() {
+                entries.push(Entry::Symlink {
+                    link: cstring(&target)?,
+                    target: cstring(source.read_link()?)?,
+                });
+                continue;
+            }
+            if metadata.is_dir() {
+                dirs.insert(target.clone());
+            } else {
+                files.push(cstring(&target)?);
+            }
+            entries.push(Entry::Bind {
+                source: cstring(&source)?,
+                target: cstring(&target)?,
+                writable: writable_workspace && source == workspace,
+            });
+        }
+        dirs.insert(root.join("proc"));
+
+        // The user namespace is created by the user the submission runs as
+        let Credentials { uid, gid } = credentials.unwrap_or_else(|| unsafe {
+            Credentials {
+                uid: libc::getuid(),
+                gid: libc::getgid(),
+            }
+        });
+        Ok(Self {
+            uid_map: CString::new(format!("{uid} {uid} 1"))?,
+            gid_map: CString::new(format!("{gid} {gid} 1"))?,
+            proc_dir: cstring(root.join("proc"))?,
+            root: cstring(&root)?,
+            dirs: dirs.iter().map(cstring).collect::<Result<_>>()?,
+            files,
+            entries,
+            workdir: cstring(&workspace)?,
+        })
+    }
+
+    /// Unshare the user, PID, network, IPC, UTS and mount namespaces, then
+    /// `pivot_root` into the planned root.
+    ///
+    /// A new PID namespace only applies to children of the caller, so this
+    /// forks: the calling process stays behind as a supervisor which mirrors
+    /// the exit status of the sandboxed process, and only returns in the
+    /// child, which becomes PID 1 of the new namespace.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called in a single-threaded child right before `exec`.
+    pub unsafe fn enter(&self) -> Result<(), SetupError> {
+        check(SetupStep::UnshareUser, libc::unshare(libc::CLONE_NEWUSER))?;
+        write_file(SetupStep::WriteSetgroups, c"/proc/self/setgroups", c"deny")?;
+        write_file(SetupStep::WriteUidMap, c"/proc/self/uid_map", &self.uid_map)?;
+        write_file(SetupStep::WriteGidMap, c"/proc/self/gid_map", &self.gid_map)?;
+        check(
+            SetupStep::UnshareNamespaces,
+            libc::unshare(
+                libc::CLONE_NEWNS
+                    | libc::CLONE_NEWPID
+                    | libc::CLONE_NEWNET
+                    | libc::CLONE_NEWIPC
+                    | libc::CLONE_NEWUTS,
+            ),
+        )?;
+
+        let pid = check(SetupStep::Fork, libc::fork())?;
+        if pid > 0 {
+            supervise(pid);
+        }
+        check(
+            SetupStep::ParentDeathSignal,
+            libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0),
+        )?;
+
+        self.pivot_root()
+    }
+
+    unsafe fn pivot_root(&self) -> Result<(), SetupError> {
+        // Keep our mounts from propagating back to the host
+        check(
+            SetupStep::MakeMountsPrivate,
+            libc::mount(
+                std::ptr::null(),
+                c"/".as_ptr(),
+                std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                std::ptr::null(),
+            ),
+        )?;
+        check(
+            SetupStep::MountRoot,
+            libc::mount(
+                c"tmpfs".as_ptr(),
+                self.root.as_ptr(),
+                c"tmpfs".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                c"mode=0755".as_ptr().cast(),
+            ),
+        )?;
+        for dir in &self.dirs {
+            check(SetupStep::CreateDirectory, libc::mkdir(dir.as_ptr(), 0o755))?;
+        }
+        for file in &self.files {
+            libc::close(check(
+                SetupStep::CreateFile,
+                libc::open(
+                    file.as_ptr(),
+                    libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+                    0o644,
+                ),
+            )?);
+        }
+        for entry in &self.entries {
+            match entry {
+                Entry::Bind {
+                    source,
+                    target,
+                    writable,
+                } => bind(source, target, *writable)?,
+                Entry::Symlink { link, target } => {
+                    check(
+                        SetupStep::CreateSymlink,
+                        libc::symlink(target.as_ptr(), link.as_ptr()),
+                    )?;
+                }
+            }
+        }
+        check(
+            SetupStep::MountProc,
+            libc::mount(
+                c"proc".as_ptr(),
+                self.proc_dir.as_ptr(),
+                c"proc".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                std::ptr::null(),
+            ),
+        )?;
+
+        check(SetupStep::ChangeDirectory, libc::chdir(self.root.as_ptr()))?;
+        check(
+            SetupStep::PivotRoot,
+            libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) as i32,
+        )?;
+        check(
+            SetupStep::DetachOldRoot,
+            libc::umount2(c".".as_ptr(), libc::MNT_DETACH),
+        )?;
+        check(
+            SetupStep::RemountReadOnly,
+            libc::mount(
+                std::ptr::null(),
+                c"/".as_ptr(),
+                std::ptr::null(),
+                libc::MS_REMOUNT
+                    | libc::MS_BIND
+                    | libc::MS_RDONLY
+                    | libc::MS_NOSUID
+                    | libc::MS_NODEV,
+                std::ptr::null(),
+            ),
+        )?;
+        check(
+            SetupStep::ChangeDirectory,
+            libc::chdir(self.workdir.as_ptr()),
+        )?;
+        Ok(())
+    }
+}
+
+/// Host paths a run in `workspace` needs to see: the existing system paths,
+/// the runtime at `exec_path` and the canonical `workspace`, sorted so that
+/// parents come before their children.
+pub fn visible_paths(workspace: &Path, exec_path: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = SYSTEM_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .filter(|path| path.symlink_metadata().is_ok())
+        .collect();
+    if let Some(runtime) = runtime_prefix(exec_path, workspace, &paths) {
+        paths.push(runtime);
+    }
+    paths.push(workspace.to_path_buf());
+    paths.sort();
+    paths
+}
+
+/// The installation prefix of an interpreter living outside the system paths,
+/// laid out as `<prefix>/bin/<executable>`, e.g. `/root/.pyenv/versions/3.12.0`,
+/// the `sys.base_prefix` of Python, for its `bin/python3.12`.
+///
+/// Only the executable is exposed when it is not laid out so and its
+/// directory is the root or holds the home directory of a user, e.g. for
+/// `~/bin/deno` or `~/deno`.
+fn runtime_prefix(exec_path: &Path, workspace: &Path, system: &[PathBuf]) -> Option<PathBuf> {
+    let exec_path = exec_path.canonicalize().ok()?;
+    if exec_path.starts_with(workspace) || system.iter().any(|p| exec_path.starts_with(p)) {
+        return None;
+    }
+    let dir = exec_path.parent()?;
+    let prefix = match dir.file_name() {
+        Some(name) if name == "bin" => dir.parent()?,
+        _ => dir,
+    };
+    if prefix == Path::new("/") || holds_home(prefix) {
+        return Some(exec_path);
+    }
+    Some(prefix.to_path_buf())
+}
+
+/// Whether `path` is or holds the home directory of a user.
+fn holds_home(path: &Path) -> bool {
+    let passwd = std::fs::read_to_string("/etc/passwd").unwrap_or_default();
+    passwd
+        .lines()
+        .filter_map(|line| line.split(':').nth(5))
+        .map(PathBuf::from)
+        .chain(std::env::var_os("HOME").map(PathBuf::from))
+        .any(|home| home.starts_with(path))
+}
+
+/// Wait for the sandboxed process and exit the same way it did.
+unsafe fn supervise(pid: libc::pid_t) -> ! {
+    libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0);
+    let mut status = 0;
+    while libc::waitpid(pid, &mut status, 0) < 0 {
+        if *libc::__errno_location() != libc::EINTR {
+            libc::_exit(127);
+        }
+    }
+    if libc::WIFSIGNALED(status) {
+        let signal = libc::WTERMSIG(status);
+        let mut set = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, signal);
+        libc::sigprocmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+        libc::signal(signal, libc::SIG_DFL);
+        libc::kill(libc::getpid(), signal);
+        libc::_exit(128 + signal);
+    }
+    libc::_exit(libc::WEXITSTATUS(status))
+}
+
+/// Bind-mount `source` at `target`, read-only unless `writable`.
+unsafe fn bind(source: &CString, target: &CString, writable: bool) -> Result<(), SetupError> {
+    check(
+        SetupStep::BindMount,
+        libc::mount(
+            source.as_ptr(),
+            target.as_ptr(),
+            std::ptr::null(),
+            libc::MS_BIND | libc::MS_REC,
+            std::ptr::null(),
+        ),
+    )?;
+    // Flags locked by the original mount must be kept when remounting in a
+    // user namespace, otherwise the kernel refuses with `EPERM`.
+    let mut stat = std::mem::zeroed::<libc::statvfs>();
+    check(
+        SetupStep::StatMount,
+        libc::statvfs(target.as_ptr(), &mut stat),
+    )?;
+    let mut flags = libc::MS_REMOUNT | libc::MS_BIND | libc::MS_NOSUID;
+    if !writable {
+        flags |= libc::MS_RDONLY;
+    }
+    for (st, ms) in [
+        (libc::ST_NODEV, libc::MS_NODEV),
+        (libc::ST_NOEXEC, libc::MS_NOEXEC),
+        (libc::ST_NOATIME, libc::MS_NOATIME),
+        (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+        (libc::ST_RELATIME, libc::MS_RELATIME),
+    ] {
+        if stat.f_flag & st != 0 {
+            flags |= ms;
+        }
+    }
+    check(
+        SetupStep::RemountReadOnly,
+        libc::mount(
+            std::ptr::null(),
+            target.as_ptr(),
+            std::ptr::null(),
+            flags,
+            std::ptr::null(),
+        ),
+    )
+    .map(drop)
+}
+
+unsafe fn write_file(
+    step: SetupStep,
+    path: &std::ffi::CStr,
+    contents: &std::ffi::CStr,
+) -> Result<(), SetupError> {
+    let fd = check(
+        step,
+        libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC),
+    )?;
+    let bytes = contents.to_bytes();
+    let written = libc::write(fd, bytes.as_ptr().cast(), bytes.len());
+    let error = SetupError::last(step);
+    libc::close(fd);
+    if written < 0 {
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn cstring<P: AsRef<Path>>(path: P) -> Result<CString> {
+    Ok(CString::new(path.as_ref().as_os_str().as_bytes())?)
+}
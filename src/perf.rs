@@ -0,0 +1,189 @@
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
+
+use crate::{
+    setup::{SetupError, SetupStep},
+    utils::{recv_fd, send_fd},
+};
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+const PERF_TYPE_SOFTWARE: u32 = 1;
+const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+const PERF_COUNT_SW_TASK_CLOCK: u64 = 1;
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+/// `perf_event_attr` bits: `disabled`, `inherit`, `exclude_kernel`,
+/// `exclude_hv` and `enable_on_exec`.
+const ATTR_FLAGS: u64 = 1 | 1 << 1 | 1 << 5 | 1 << 6 | 1 << 12;
+
+/// Instructions retired per nanosecond of task clock assumed when only
+/// software counters are available.
+pub const NOMINAL_INSTRUCTIONS_PER_NS: u64 = 1;
+
+/// The first version of `struct perf_event_attr`, which the kernel extends
+/// with zeroes.
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    kind: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+}
+
+/// Instructions retired by a run in user space.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstructionCount {
+    pub count: u64,
+    /// Estimated from the task clock at [`NOMINAL_INSTRUCTIONS_PER_NS`],
+    /// hardware counters being unavailable, e.g. in virtual machines.
+    pub estimated: bool,
+}
+
+/// A counter opened by the child before `exec`, so that it counts the run
+/// from its first instruction, including its threads and children.
+pub struct InstructionCounter {
+    state: CounterState,
+}
+
+enum CounterState {
+    /// Waiting for the child to send the counter over the socket.
+    Connecting(OwnedFd),
+    Counting {
+        counter: OwnedFd,
+        estimated: bool,
+    },
+    /// The child could not open a counter.
+    Unavailable,
+}
+
+impl InstructionCounter {
+    /// Create the counter and the socket end to hand to the child, which
+    /// sends it with [`open_counter`].
+    pub fn new() -> io::Result<(Self, OwnedFd)> {
+        let mut fds = [0; 2];
+        if unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let (parent, child) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        let counter = Self {
+            state: CounterState::Connecting(parent),
+        };
+        Ok((counter, child))
+    }
+
+    /// The instructions counted so far, once the child has sent its counter.
+    ///
+    /// The counts of threads and children are only added once they exit.
+    pub fn read(&mut self) -> Option<InstructionCount> {
+        if let CounterState::Connecting(socket) = &self.state {
+            // Sent before `exec`, so it is there once the child runs
+            let mut pending = libc::pollfd {
+                fd: socket.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut pending, 1, 0) } <= 0 {
+                return None;
+            }
+            self.state = match recv_fd(socket.as_raw_fd()) {
+                Ok(Some(counter)) => {
+                    // Followed by the kind of the counter
+                    let mut kind = 0u32;
+                    let read = unsafe {
+                        libc::recv(
+                            socket.as_raw_fd(),
+                            (&mut kind as *mut u32).cast(),
+                            size_of::<u32>(),
+                            libc::MSG_DONTWAIT,
+                        )
+                    };
+                    CounterState::Counting {
+                        counter,
+                        estimated: read != size_of::<u32>() as isize || kind != PERF_TYPE_HARDWARE,
+                    }
+                }
+                _ => CounterState::Unavailable,
+            };
+        }
+        let CounterState::Counting { counter, estimated } = &self.state else {
+            return None;
+        };
+        let value = read_u64(counter.as_raw_fd())?;
+        Some(InstructionCount {
+            count: match estimated {
+                true => value.saturating_mul(NOMINAL_INSTRUCTIONS_PER_NS),
+                false => value,
+            },
+            estimated: *estimated,
+        })
+    }
+}
+
+/// Open an instruction counter on the calling process, falling back to its
+/// task clock, and send it over `socket`. It starts counting on `exec`.
+///
+/// # Safety
+///
+/// Must only be called in a forked child right before `exec`.
+pub unsafe fn open_counter(socket: RawFd) -> Result<(), SetupError> {
+    let (kind, counter) = match perf_event_open(PERF_TYPE_HARDWARE, PERF_COUNT_HW_INSTRUCTIONS) {
+        counter if counter >= 0 => (PERF_TYPE_HARDWARE, counter),
+        _ => (
+            PERF_TYPE_SOFTWARE,
+            perf_event_open(PERF_TYPE_SOFTWARE, PERF_COUNT_SW_TASK_CLOCK),
+        ),
+    };
+    if counter < 0 {
+        return Err(SetupError::last(SetupStep::OpenCounter));
+    }
+    send_fd(socket, counter).map_err(|e| SetupError::io(SetupStep::SendCounter, e))?;
+    if libc::send(socket, (&kind as *const u32).cast(), size_of::<u32>(), 0) < 0 {
+        return Err(SetupError::last(SetupStep::SendCounter));
+    }
+    libc::close(counter);
+    libc::close(socket);
+    Ok(())
+}
+
+unsafe fn perf_event_open(kind: u32, config: u64) -> RawFd {
+    let attr = PerfEventAttr {
+        kind,
+        size: size_of::<PerfEventAttr>() as u32,
+        config,
+        flags: ATTR_FLAGS,
+        ..Default::default()
+    };
+    libc::syscall(
+        libc::SYS_perf_event_open,
+        &attr,
+        0,
+        -1,
+        -1,
+        PERF_FLAG_FD_CLOEXEC,
+    ) as RawFd
+}
+
+fn read_u64(fd: RawFd) -> Option<u64> {
+    let mut value = 0u64;
+    let read = unsafe { libc::read(fd, (&mut value as *mut u64).cast(), size_of::<u64>()) };
+    (read == size_of::<u64>() as isize).then_some(value)
+}
@@ -0,0 +1,697 @@
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{Read, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::{ffi::OsStrExt, process::CommandExt},
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+};
+
+use anyhow::Result;
+use seccompiler::BpfProgram;
+use which::which;
+
+use crate::{
+    cgroup::Cgroup,
+    config::{JudgeOptions, SandboxMode},
+    namespace::{visible_paths, NamespaceSandbox},
+    perf::{open_counter, InstructionCounter},
+    seccomp::{
+        install, install_notifying, ArgCondition, ArgOp, Filters, SeccompPolicy, ViolationListener,
+    },
+    setup::{check, SetupError, SetupErrors, SetupStep},
+    trace::{fork_traced, SyscallTrace},
+    user::Credentials,
+    utils::send_fd,
+};
+
+/// Maximum number of tasks (processes and threads) allowed in the cgroup of a run.
+const PIDS_LIMIT: u64 = 128;
+
+/// Exit code of `nsjail` failing itself, e.g. to set up the jail.
+const NSJAIL_FAILURE: i32 = 255;
+
+/// A single run of a submission, before isolation is applied.
+pub struct RunSpec<'a> {
+    pub workspace: &'a Path,
+    pub exec_path: &'a str,
+    pub args: &'a [&'a str],
+    pub options: &'a JudgeOptions,
+    /// User to run as, already leased from [`JudgeOptions::run_as`].
+    pub credentials: Option<Credentials>,
+    /// Core to pin the run to, already reserved by a scheduler.
+    pub core: Option<usize>,
+    pub stdin: File,
+    pub stdout: File,
+}
+
+/// A spawned run and everything the judge needs to supervise it.
+pub struct Spawned {
+    pub child: Child,
+    pub cgroup: Option<Cgroup>,
+    pub seccomp: Option<ViolationListener>,
+    pub setup_errors: SetupErrors,
+    /// The child is a wrapper which reports the submission being killed by a
+    /// signal as exit code `128 + signal`.
+    pub wrapped: bool,
+    /// How the wrapper reports failing to set up the sandbox itself.
+    pub wrapper_report: Option<WrapperReport>,
+    pub trace: Option<SyscallTrace>,
+    pub instructions: Option<InstructionCounter>,
+}
+
+/// Where a wrapper reports on its own setup, telling its failures from the
+/// submission's, read once it exited.
+pub enum WrapperReport {
+    /// `bwrap --info-fd`, written once the sandboxed child is created.
+    Bubblewrap(File),
+    /// `nsjail --log_fd`, holding the messages of `nsjail` itself.
+    Nsjail(File),
+}
+
+/// Turns a run into a spawned, isolated and limited child.
+///
+/// The child must lead its own process group and pipe its standard error,
+/// which the judge drains.
+pub trait Sandbox: Send + Sync {
+    fn spawn(&self, spec: RunSpec<'_>) -> Result<Spawned>;
+}
+
+/// Isolation built by the judge itself with namespaces, `seccomp` and
+/// `setrlimit`, as selected by [`JudgeOptions::sandbox`].
+///
+/// The only backend honoring [`JudgeOptions::unsafe_mode`], reporting
+/// which system call a submission was stopped at and counting instructions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Native;
+
+/// Isolation by the locally installed bubblewrap (`bwrap`).
+///
+/// Runs in fresh namespaces, including an empty network namespace, with a
+/// read-only view of the system paths and the workspace.
+#[derive(Debug, Clone)]
+pub struct Bubblewrap {
+    pub path: PathBuf,
+    /// Passed to `bwrap` before the command.
+    pub extra_args: Vec<String>,
+}
+
+/// Isolation by the locally installed `nsjail`.
+///
+/// `nsjail` compiles its own `seccomp` policies from Kafel, into which the
+/// [`SeccompPolicy`] of the options is written.
+#[derive(Debug, Clone)]
+pub struct Nsjail {
+    pub path: PathBuf,
+    /// Passed to `nsjail` before the command.
+    pub extra_args: Vec<String>,
+}
+
+/// The sandbox called `name`: `native`, `bwrap` or `nsjail`.
+///
+/// Fails when the wrapper is not installed, rather than falling back to
+/// another sandbox.
+pub fn by_name(name: &str) -> Result<Box<dyn Sandbox>> {
+    match name {
+        "native" => Ok(Box::new(Native)),
+        "bwrap" => Ok(Box::new(Bubblewrap::new()?)),
+        "nsjail" => Ok(Box::new(Nsjail::new()?)),
+        name => anyhow::bail!("Unknown sandbox: {}", name),
+    }
+}
+
+impl Bubblewrap {
+    /// Find `bwrap` in `PATH`.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            path: which("bwrap")?,
+            extra_args: vec![],
+        })
+    }
+}
+
+impl Nsjail {
+    /// Find `nsjail` in `PATH`.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            path: which("nsjail")?,
+            extra_args: vec![],
+        })
+    }
+}
+
+impl Sandbox for Native {
+    fn spawn(&self, spec: RunSpec<'_>) -> Result<Spawned> {
+        let options = spec.options;
+        let mut command = command(spec.exec_path, &spec)?;
+        command.args(spec.args);
+        let (setup_errors, setup_pipe) = SetupErrors::new()?;
+        let error_fd = setup_pipe.as_raw_fd();
+        let (cgroup, cgroup_procs) = join_cgroup(&mut command, options, error_fd)?;
+        pin_to_core(&mut command, spec.core, error_fd);
+
+        let no_sys_as_limits = options.no_startup_limits;
+        let seccomp_enabled = !no_sys_as_limits && !options.unsafe_mode;
+        // A tracer reports rejected system calls instead of the listener
+        let (trace, trace_pipe) = match options.trace_syscalls {
+            Some(limit) if seccomp_enabled => {
+                let (trace, pipe) = SyscallTrace::new(limit)?;
+                (Some(trace), Some(pipe))
+            }
+            _ => (None, None),
+        };
+        let (seccomp, seccomp_socket) = match seccomp_enabled && trace.is_none() {
+            false => (None, None),
+            true => {
+                let (listener, socket) = ViolationListener::new()?;
+                (Some(listener), Some(socket))
+            }
+        };
+        let (instructions, counter_socket) = match options.counts_instructions() {
+            true => {
+                let (counter, socket) = InstructionCounter::new()?;
+                (Some(counter), Some(socket))
+            }
+            false => (None, None),
+        };
+        let counter_fd = counter_socket.as_ref().map_or(-1, AsRawFd::as_raw_fd);
+        let socket_fd = seccomp_socket.as_ref().map_or(-1, AsRawFd::as_raw_fd);
+        let trace_fd = trace_pipe.as_ref().map_or(-1, AsRawFd::as_raw_fd);
+        // The forked child execs the path `command` holds, at the same address
+        let exec_address = command.get_program().as_bytes().as_ptr() as u64;
+        let mut filters = match (&seccomp_socket, &trace_pipe) {
+            // The listener is sent to us once the allowlist is already in place
+            (Some(_), _) => policy(options)
+                .allow_exec_of(exec_address)
+                .allow_if(
+                    libc::SYS_sendmsg,
+                    vec![ArgCondition::new(0, ArgOp::Eq, socket_fd as u64)],
+                )
+                .compile()?,
+            (None, Some(_)) => policy(options)
+                .allow_exec_of(exec_address)
+                .compile_tracing()?,
+            (None, None) => Filters::default(),
+        };
+        let memory_limit = options.memory_limit;
+        let isolate_network = options.isolate_network;
+        let rlimits = rlimits(options);
+        let credentials = spec.credentials;
+        let namespaces = match options.sandbox {
+            SandboxMode::Namespaces => Some(NamespaceSandbox::new(
+                spec.workspace,
+                spec.exec_path,
+                credentials,
+                options.writable_workspace,
+            )?),
+            SandboxMode::Basic => None,
+        };
+        if !options.unsafe_mode {
+            unsafe {
+                let mut setup = move || -> Result<(), SetupError> {
+                    // Close all file descriptors except for stdin, stdout, and stderr,
+                    // along with the error pipe of `Command::spawn`, which the supervisors
+                    // forked below would hold open for the whole run: failures are
+                    // reported on `error_fd` only
+                    for fd in (3..1024)
+                        .filter(|fd| ![socket_fd, error_fd, trace_fd, counter_fd].contains(fd))
+                    {
+                        libc::close(fd);
+                    }
+                    // Prevent child from gaining new privileges
+                    check(
+                        SetupStep::NoNewPrivileges,
+                        libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0),
+                    )?;
+                    if let Some(namespaces) = &namespaces {
+                        // The user namespace maps the user that creates it, so switch first
+                        if let Some(credentials) = &credentials {
+                            credentials.switch()?;
+                        }
+                        // Enter the full namespace sandbox, forking into a new PID namespace
+                        namespaces.enter()?;
+                    } else {
+                        // Unshare the mount namespace to prevent child from gaining new mounts
+                        check(SetupStep::UnshareMount, libc::unshare(libc::CLONE_NEWNS))?;
+                        // An empty network namespace leaves nothing to connect to
+                        if isolate_network {
+                            check(SetupStep::UnshareNetwork, libc::unshare(libc::CLONE_NEWNET))?;
+                        }
+                        // Drop root once it is no longer needed
+                        if let Some(credentials) = &credentials {
+                            credentials.switch()?;
+                        }
+                    }
+                    if trace_fd >= 0 {
+                        // Only the tracee goes on, the tracer stays outside of the limits
+                        fork_traced(trace_fd)?;
+                    }
+                    // Limit before installing the filters, which then only see `exec`
+                    set_rlimits(&rlimits)?;
+                    // Opened before the filters, which forbid `perf_event_open`
+                    if counter_fd >= 0 {
+                        open_counter(counter_fd)?;
+                    }
+                    // Set memory limit
+                    if !no_sys_as_limits {
+                        let limit = libc::rlimit {
+                            rlim_cur: memory_limit,
+                            rlim_max: memory_limit,
+                        };
+                        check(
+                            SetupStep::MemoryLimit,
+                            libc::setrlimit(libc::RLIMIT_AS, &limit),
+                        )?;
+                        filters.bind_pid();
+                        if let Some((allowlist, denials)) = filters.programs.split_last() {
+                            for filter in denials {
+                                install(filter)
+                                    .map_err(|e| SetupError::io(SetupStep::SeccompFilter, e))?;
+                            }
+                            if socket_fd < 0 {
+                                install(allowlist)
+                                    .map_err(|e| SetupError::io(SetupStep::SeccompFilter, e))?;
+                            } else {
+                                let listener = install_notifying(allowlist)
+                                    .map_err(|e| SetupError::io(SetupStep::SeccompListener, e))?;
+                                send_fd(socket_fd, listener)
+                                    .map_err(|e| SetupError::io(SetupStep::SendListener, e))?;
+                                libc::close(listener);
+                                libc::close(socket_fd);
+                            }
+                        }
+                    }
+                    Ok(())
+                };
+                command.pre_exec(move || {
+                    setup().unwrap_or_else(|e| e.exit(error_fd));
+                    Ok(())
+                });
+            }
+        } else if counter_fd >= 0 {
+            unsafe {
+                command.pre_exec(move || {
+                    open_counter(counter_fd).unwrap_or_else(|e| e.exit(error_fd));
+                    Ok(())
+                });
+            }
+        }
+
+        let child = spawn(&mut command)?;
+        drop(cgroup_procs);
+        drop(seccomp_socket);
+        drop(trace_pipe);
+        drop(counter_socket);
+        drop(setup_pipe);
+        Ok(Spawned {
+            child,
+            cgroup,
+            seccomp,
+            setup_errors,
+            // The tracer exits like a wrapper
+            wrapped: trace.is_some(),
+            wrapper_report: None,
+            trace,
+            instructions,
+        })
+    }
+}
+
+impl Sandbox for Bubblewrap {
+    fn spawn(&self, spec: RunSpec<'_>) -> Result<Spawned> {
+        let options = spec.options;
+        let mut command = command(&self.path, &spec)?;
+        // The submission becomes PID 1 of its namespace, dying along with `bwrap`
+        command.args(["--unshare-all", "--die-with-parent", "--as-pid-1"]);
+        let workspace = spec.workspace.canonicalize()?;
+        for path in visible_paths(&workspace, spec.exec_path.as_ref()) {
+            match path.read_link() {
+                Ok(target) => command.arg("--symlink").arg(target).arg(&path),
+                Err(_) if options.writable_workspace && path == workspace => {
+                    command.arg("--bind").arg(&path).arg(&path)
+                }
+                Err(_) => command.arg("--ro-bind").arg(&path).arg(&path),
+            };
+        }
+        command
+            .args(["--proc", "/proc", "--chdir"])
+            .arg(spec.workspace);
+        if let Some(credentials) = spec.credentials {
+            command
+                .args(["--uid", &credentials.uid.to_string()])
+                .args(["--gid", &credentials.gid.to_string()]);
+        }
+
+        // `bwrap` needs processes of its own, so rather than `RLIMIT_NPROC`
+        // the filters forbid the submission any
+        let deny_processes = match options.limits.as_ref().and_then(|l| l.processes) {
+            None => false,
+            Some(0) if !options.no_startup_limits => true,
+            Some(processes) => anyhow::bail!(
+                "bwrap cannot limit a run to {} processes, only forbid them with the startup limits",
+                processes
+            ),
+        };
+
+        // `bwrap` reads each program from a descriptor it inherits
+        let mut programs = vec![];
+        if !options.no_startup_limits {
+            // `bwrap` execs the submission itself once the filters are in place
+            let mut policy = policy(options).allow(libc::SYS_execve);
+            if deny_processes {
+                policy = policy.deny_processes();
+            }
+            let mut filters = policy.compile_trapping()?;
+            filters.bind_pid_to(1);
+            for filter in &filters.programs {
+                let program = program_fd(filter)?;
+                command
+                    .arg("--add-seccomp-fd")
+                    .arg(program.as_raw_fd().to_string());
+                programs.push(program);
+            }
+        }
+        // Nothing written to it means `bwrap` failed before the submission
+        let (info, info_pipe) = report_pipe()?;
+        command
+            .arg("--info-fd")
+            .arg(info_pipe.as_raw_fd().to_string());
+        command
+            .args(&self.extra_args)
+            .arg("--")
+            .arg(spec.exec_path)
+            .args(spec.args);
+
+        let (setup_errors, setup_pipe) = SetupErrors::new()?;
+        let (cgroup, cgroup_procs) = join_cgroup(&mut command, options, setup_pipe.as_raw_fd())?;
+        pin_to_core(&mut command, spec.core, setup_pipe.as_raw_fd());
+        let mut rlimits: Vec<_> = rlimits(options)
+            .into_iter()
+            .filter(|(resource, ..)| *resource != libc::RLIMIT_NPROC)
+            .collect();
+        // Inherited from `bwrap`, which needs far less
+        if !options.no_startup_limits {
+            let memory_limit = Some(options.memory_limit);
+            rlimits.push((libc::RLIMIT_AS, memory_limit, memory_limit));
+        }
+        let error_fd = setup_pipe.as_raw_fd();
+        unsafe {
+            command.pre_exec(move || {
+                set_rlimits(&rlimits).unwrap_or_else(|e| e.exit(error_fd));
+                Ok(())
+            });
+        }
+
+        let child = spawn(&mut command)?;
+        drop(cgroup_procs);
+        drop(setup_pipe);
+        drop(info_pipe);
+        drop(programs);
+        Ok(Spawned {
+            child,
+            cgroup,
+            seccomp: None,
+            setup_errors,
+            wrapped: true,
+            wrapper_report: Some(WrapperReport::Bubblewrap(info)),
+            trace: None,
+            instructions: None,
+        })
+    }
+}
+
+impl Sandbox for Nsjail {
+    fn spawn(&self, spec: RunSpec<'_>) -> Result<Spawned> {
+        let options = spec.options;
+        let limits = options.limits.clone().unwrap_or_default();
+        let megabytes = |bytes: u64| bytes.div_ceil(1024 * 1024).to_string();
+        let mut command = command(&self.path, &spec)?;
+        command.args(["--mode", "o", "--quiet", "--time_limit", "0"]);
+        command
+            .args([
+                "--rlimit_cpu",
+                &limits.cpu_seconds(options.cpu_time_cap()).to_string(),
+            ])
+            .args(["--rlimit_fsize", &megabytes(limits.file_size + 1)])
+            .args(["--rlimit_nofile", &limits.open_files.to_string()])
+            .args(["--rlimit_core", "0"]);
+        match limits.processes {
+            Some(processes) => command.args(["--rlimit_nproc", &processes.to_string()]),
+            None => command.args(["--rlimit_nproc", "hard"]),
+        };
+        if !options.no_startup_limits {
+            command.args(["--rlimit_as", &megabytes(options.memory_limit)]);
+            // The submission is PID 1 of its namespace
+            command
+                .arg("--seccomp_string")
+                .arg(policy(options).allow(libc::SYS_execve).to_kafel(1));
+        } else {
+            // Rather than the default of `nsjail`
+            command.args(["--rlimit_as", "max"]);
+        }
+        match limits.stack {
+            Some(stack) => command.args(["--rlimit_stack", &megabytes(stack)]),
+            None => command.args(["--rlimit_stack", "max"]),
+        };
+        let workspace = spec.workspace.canonicalize()?;
+        for path in visible_paths(&workspace, spec.exec_path.as_ref()) {
+            match path.read_link() {
+                Ok(target) => {
+                    command
+                        .arg("--symlink")
+                        .arg(format!("{}:{}", target.display(), path.display()))
+                }
+                Err(_) if options.writable_workspace && path == workspace => {
+                    command.arg("--bindmount").arg(&path)
+                }
+                Err(_) => command.arg("--bindmount_ro").arg(&path),
+            };
+        }
+        command.arg("--cwd").arg(spec.workspace);
+        let (log, log_pipe) = report_pipe()?;
+        command
+            .arg("--log_fd")
+            .arg(log_pipe.as_raw_fd().to_string());
+        if let Some(credentials) = spec.credentials {
+            command
+                .args(["--user", &credentials.uid.to_string()])
+                .args(["--group", &credentials.gid.to_string()]);
+        }
+        command
+            .args(&self.extra_args)
+            .arg("--")
+            .arg(spec.exec_path)
+            .args(spec.args);
+
+        let (setup_errors, setup_pipe) = SetupErrors::new()?;
+        let (cgroup, cgroup_procs) = join_cgroup(&mut command, options, setup_pipe.as_raw_fd())?;
+        pin_to_core(&mut command, spec.core, setup_pipe.as_raw_fd());
+        let child = spawn(&mut command)?;
+        drop(cgroup_procs);
+        drop(setup_pipe);
+        drop(log_pipe);
+        Ok(Spawned {
+            child,
+            cgroup,
+            seccomp: None,
+            setup_errors,
+            wrapped: true,
+            wrapper_report: Some(WrapperReport::Nsjail(log)),
+            trace: None,
+            instructions: None,
+        })
+    }
+}
+
+impl WrapperReport {
+    /// The failure of the wrapper itself, if it is why it exited with
+    /// `status`, writing `stderr`.
+    pub fn failure(&self, status: ExitStatus, stderr: &str) -> Option<String> {
+        let (Self::Bubblewrap(file) | Self::Nsjail(file)) = self;
+        let mut report = vec![];
+        // Never blocks, and holds all there is once the wrapper exited
+        let _ = (&*file).read_to_end(&mut report);
+        let report = String::from_utf8_lossy(&report);
+        let code = status.code()?;
+        match self {
+            Self::Bubblewrap(_) if code != 0 && report.is_empty() => {
+                Some(format!("bwrap failed: {}", stderr.trim()))
+            }
+            Self::Nsjail(_) if code == NSJAIL_FAILURE && !report.is_empty() => {
+                Some(format!("nsjail failed: {}", report.trim()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A pipe whose write end a wrapper inherits and whose read end never
+/// blocks.
+fn report_pipe() -> Result<(File, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let (read, write) = unsafe { (File::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    if unsafe { libc::fcntl(read.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok((read, write))
+}
+
+/// A command running `program` in the workspace of `spec` with its standard
+/// streams, an empty environment and its own process group.
+fn command<S: AsRef<OsStr>>(program: S, spec: &RunSpec<'_>) -> Result<Command> {
+    let mut command = Command::new(program);
+    command
+        .env_clear()
+        .current_dir(spec.workspace)
+        .stdin(Stdio::from(spec.stdin.try_clone()?))
+        .stdout(Stdio::from(spec.stdout.try_clone()?))
+        .stderr(Stdio::piped())
+        // Lead a new process group, so that the whole tree can be killed
+        .process_group(0);
+    Ok(command)
+}
+
+/// Spawn `command`, reporting a failure to fork or `exec` as a [`SetupError`].
+///
+/// The hooks of the setup report on their own pipe and exit instead, see
+/// [`SetupError::exit`].
+fn spawn(command: &mut Command) -> Result<Child> {
+    command.spawn().map_err(|e| match e.raw_os_error() {
+        Some(errno) => SetupError::new(SetupStep::Spawn, errno).into(),
+        None => e.into(),
+    })
+}
+
+/// Account resources of the run with cgroup v2 when possible, falling back
+/// to polling statm.
+///
+/// Also returns the open `cgroup.procs`, to be closed once the child spawned.
+fn join_cgroup(
+    command: &mut Command,
+    options: &JudgeOptions,
+    error_fd: RawFd,
+) -> Result<(Option<Cgroup>, Option<File>)> {
+    let cgroup = Cgroup::create(options.memory_limit, PIDS_LIMIT);
+    let cgroup_procs = cgroup.as_ref().map(Cgroup::procs_file).transpose()?;
+    if let Some(procs) = &cgroup_procs {
+        let fd = procs.as_raw_fd();
+        unsafe {
+            command.pre_exec(move || {
+                // Writing `0` to `cgroup.procs` moves the calling process into the cgroup
+                if libc::write(fd, b"0".as_ptr().cast(), 1) < 0 {
+                    SetupError::last(SetupStep::JoinCgroup).exit(error_fd);
+                }
+                Ok(())
+            })
+        };
+    }
+    Ok((cgroup, cgroup_procs))
+}
+
+/// Restrict the child and everything it spawns to `core`.
+fn pin_to_core(command: &mut Command, core: Option<usize>, error_fd: RawFd) {
+    let Some(core) = core else {
+        return;
+    };
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    unsafe {
+        libc::CPU_SET(core, &mut set);
+        command.pre_exec(move || {
+            if libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) < 0 {
+                SetupError::last(SetupStep::SetAffinity).exit(error_fd);
+            }
+            Ok(())
+        });
+    }
+}
+
+fn policy(options: &JudgeOptions) -> SeccompPolicy {
+    options
+        .seccomp_policy
+        .clone()
+        .unwrap_or_else(SeccompPolicy::strict)
+}
+
+/// Resources limited with `setrlimit`, with their soft and hard limits.
+fn rlimits(options: &JudgeOptions) -> Vec<(libc::__rlimit_resource_t, Option<u64>, Option<u64>)> {
+    let limits = options.limits.clone().unwrap_or_default();
+    let cpu_seconds = limits.cpu_seconds(options.cpu_time_cap());
+    vec![
+        (libc::RLIMIT_STACK, limits.stack, limits.stack),
+        (
+            libc::RLIMIT_NOFILE,
+            Some(limits.open_files),
+            Some(limits.open_files),
+        ),
+        (libc::RLIMIT_NPROC, limits.processes, limits.processes),
+        // One more byte than allowed, to tell a full output from an exceeded one
+        (
+            libc::RLIMIT_FSIZE,
+            Some(limits.file_size + 1),
+            Some(limits.file_size + 1),
+        ),
+        // The hard limit is a second later, so that `SIGXCPU` arrives first
+        (libc::RLIMIT_CPU, Some(cpu_seconds), Some(cpu_seconds + 1)),
+        // Disable core dumps
+        (libc::RLIMIT_CORE, Some(0), Some(0)),
+    ]
+}
+
+/// Apply `rlimits` in a forked child.
+unsafe fn set_rlimits(
+    rlimits: &[(libc::__rlimit_resource_t, Option<u64>, Option<u64>)],
+) -> Result<(), SetupError> {
+    for &(resource, soft, hard) in rlimits {
+        set_rlimit(resource, soft, hard)
+            .map_err(|e| SetupError::io(SetupStep::ResourceLimit, e).detail(resource))?;
+    }
+    Ok(())
+}
+
+/// Set a resource limit, `None` meaning unlimited.
+///
+/// Limits are clamped to the current hard limit, which only privileged
+/// processes may raise.
+unsafe fn set_rlimit(
+    resource: libc::__rlimit_resource_t,
+    soft: Option<u64>,
+    hard: Option<u64>,
+) -> std::io::Result<()> {
+    let mut current = std::mem::zeroed::<libc::rlimit>();
+    if libc::getrlimit(resource, &mut current) != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let hard = hard.unwrap_or(libc::RLIM_INFINITY).min(current.rlim_max);
+    let limit = libc::rlimit {
+        rlim_cur: soft.unwrap_or(libc::RLIM_INFINITY).min(hard),
+        rlim_max: hard,
+    };
+    if libc::setrlimit(resource, &limit) != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A memory file holding the raw instructions of `filter`, inherited by the
+/// child and read from its start.
+fn program_fd(filter: &BpfProgram) -> Result<OwnedFd> {
+    let fd = unsafe { libc::memfd_create(c"seccomp".as_ptr(), 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    let bytes = unsafe {
+        std::slice::from_raw_parts(filter.as_ptr().cast::<u8>(), size_of_val(filter.as_slice()))
+    };
+    file.write_all(bytes)?;
+    if unsafe { libc::lseek(fd, 0, libc::SEEK_SET) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(file.into())
+}
@@ -0,0 +1,113 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+
+/// Hands out exclusive CPU cores to concurrent runs, so that submissions
+/// sharing the host do not skew each other's timing.
+///
+/// Runs queue until a core is free. Jobs made of several runs, such as the
+/// submissions of the engine, can be admitted with [`Self::admit`] to bound
+/// how many of them are in flight.
+#[derive(Debug)]
+pub struct CoreScheduler {
+    slots: usize,
+    cores: Mutex<Vec<usize>>,
+    free: Semaphore,
+    jobs: Arc<Semaphore>,
+}
+
+/// A core reserved for a run, freed on drop.
+#[derive(Debug)]
+pub struct CoreSlot<'a> {
+    scheduler: &'a CoreScheduler,
+    core: usize,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl CoreScheduler {
+    /// A slot for each core the judge may run on.
+    pub fn host() -> Result<Self> {
+        Self::with_cores(allowed_cores()?)
+    }
+
+    /// `slots` slots on the last cores the judge may run on, leaving the
+    /// first ones to the judge itself and the rest of the system.
+    pub fn new(slots: usize) -> Result<Self> {
+        let cores = allowed_cores()?;
+        if slots == 0 || slots > cores.len() {
+            anyhow::bail!("Cannot schedule {} slots on {} cores", slots, cores.len());
+        }
+        Self::with_cores(cores[cores.len() - slots..].to_vec())
+    }
+
+    /// A slot for each of the given cores.
+    pub fn with_cores(cores: Vec<usize>) -> Result<Self> {
+        if cores.is_empty() {
+            anyhow::bail!("No cores to schedule on");
+        }
+        let slots = cores.len();
+        Ok(Self {
+            slots,
+            cores: Mutex::new(cores),
+            free: Semaphore::new(slots),
+            jobs: Arc::new(Semaphore::new(slots)),
+        })
+    }
+
+    /// Number of runs which may execute at once.
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Wait for a free core.
+    pub async fn acquire(&self) -> CoreSlot<'_> {
+        let permit = self
+            .free
+            .acquire()
+            .await
+            .expect("semaphore is never closed");
+        let core = self.cores.lock().unwrap().pop().expect("a core per permit");
+        CoreSlot {
+            scheduler: self,
+            core,
+            _permit: permit,
+        }
+    }
+
+    /// Wait until fewer jobs than slots are in flight. Each admitted job is
+    /// then sure to get a core for each of its runs in turn.
+    pub async fn admit(&self) -> OwnedSemaphorePermit {
+        self.jobs
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed")
+    }
+}
+
+impl CoreSlot<'_> {
+    pub fn core(&self) -> usize {
+        self.core
+    }
+}
+
+impl Drop for CoreSlot<'_> {
+    fn drop(&mut self) {
+        self.scheduler.cores.lock().unwrap().push(self.core);
+    }
+}
+
+/// Cores in the affinity mask of the judge.
+fn allowed_cores() -> io::Result<Vec<usize>> {
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    if unsafe { libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut set) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((0..libc::CPU_SETSIZE as usize)
+        .filter(|core| unsafe { libc::CPU_ISSET(*core, &set) })
+        .collect())
+}
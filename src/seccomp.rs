@@ -0,0 +1,765 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    task::{Context, Poll},
+};
+
+use anyhow::Result;
+use seccompiler::{
+    BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter,
+    SeccompRule, TargetArch,
+};
+
+use tokio::io::{unix::AsyncFd, Interest};
+
+use crate::{compile::Language, trace::TRACE_REJECTED, utils::recv_fd};
+
+/// `SECCOMP_IOCTL_NOTIF_RECV`, i.e. `_IOWR('!', 0, struct seccomp_notif)`.
+const SECCOMP_IOCTL_NOTIF_RECV: libc::c_ulong = 0xc050_2100;
+
+/// BPF instruction returning an immediate value.
+const BPF_RET_K: u16 = 0x06;
+
+/// Stands for the PID of the sandboxed process in a condition of a policy.
+///
+/// Never a valid PID itself, it is left out of the compiled filters, whose
+/// comparisons with it [`Filters::bind_pid`] sets once the PID is known.
+pub const SELF_PID: u64 = u32::MAX as u64;
+
+/// Values compiled in place of [`SELF_PID`], the instructions which differ
+/// between them being the comparisons with it.
+const PID_STAND_INS: [u64; 2] = [0, 1];
+
+/// System calls every submission needs to start, read its input and write its output.
+const BASE_SYSCALLS: &[i64] = &[
+    libc::SYS_read,
+    libc::SYS_readv,
+    libc::SYS_pread64,
+    libc::SYS_close,
+    libc::SYS_fstat,
+    libc::SYS_stat,
+    libc::SYS_lstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_lseek,
+    libc::SYS_access,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+    libc::SYS_readlink,
+    libc::SYS_readlinkat,
+    libc::SYS_getcwd,
+    libc::SYS_ioctl,
+    libc::SYS_fcntl,
+    libc::SYS_mmap,
+    libc::SYS_mprotect,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_madvise,
+    libc::SYS_brk,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_futex,
+    libc::SYS_arch_prctl,
+    libc::SYS_set_tid_address,
+    libc::SYS_set_robust_list,
+    libc::SYS_rseq,
+    libc::SYS_getrandom,
+    libc::SYS_uname,
+    libc::SYS_sysinfo,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_sched_yield,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_getres,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_gettimeofday,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+];
+
+/// Comparison applied to a system call argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ArgOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Compare the argument masked with the given bits.
+    MaskedEq(u64),
+}
+
+/// Condition on the `arg`-th (0-based) argument of a system call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArgCondition {
+    pub arg: u8,
+    pub op: ArgOp,
+    pub value: u64,
+}
+
+impl ArgCondition {
+    pub fn new(arg: u8, op: ArgOp, value: u64) -> Self {
+        Self { arg, op, value }
+    }
+}
+
+/// A system call allowed when all of its conditions hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyscallRule {
+    pub syscall: i64,
+    pub conditions: Vec<ArgCondition>,
+}
+
+/// A system call failing with `errno` when all of its conditions hold,
+/// instead of killing the process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeniedSyscall {
+    pub syscall: i64,
+    pub conditions: Vec<ArgCondition>,
+    pub errno: i32,
+}
+
+/// Allowlist of system calls a submission may use.
+///
+/// Any system call not matched by a rule kills the process. Several rules for
+/// the same system call allow it when any of them matches.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SeccompPolicy {
+    pub allow: Vec<SyscallRule>,
+    pub deny: Vec<DeniedSyscall>,
+}
+
+impl SeccompPolicy {
+    /// The strict default allowlist.
+    ///
+    /// Files may only be opened read-only and only standard output and error
+    /// may be written to.
+    pub fn strict() -> Self {
+        let read_only = ArgOp::MaskedEq((libc::O_ACCMODE | libc::O_CREAT | libc::O_TRUNC) as u64);
+        Self::default()
+            .allow_all(BASE_SYSCALLS)
+            .allow_if(
+                libc::SYS_openat,
+                vec![ArgCondition::new(2, read_only, libc::O_RDONLY as u64)],
+            )
+            .allow_if(
+                libc::SYS_open,
+                vec![ArgCondition::new(1, read_only, libc::O_RDONLY as u64)],
+            )
+            .allow_if(libc::SYS_write, vec![ArgCondition::new(0, ArgOp::Eq, 1)])
+            .allow_if(libc::SYS_write, vec![ArgCondition::new(0, ArgOp::Eq, 2)])
+            .allow_if(libc::SYS_writev, vec![ArgCondition::new(0, ArgOp::Eq, 1)])
+            .allow_if(libc::SYS_writev, vec![ArgCondition::new(0, ArgOp::Eq, 2)])
+            // `abort` and `raise` signal the calling process
+            .allow_if(
+                libc::SYS_tgkill,
+                vec![ArgCondition::new(0, ArgOp::Eq, SELF_PID)],
+            )
+            // `getrlimit` and `setrlimit`, but not on other processes of the user
+            .allow_if(
+                libc::SYS_prlimit64,
+                vec![ArgCondition::new(0, ArgOp::Eq, 0)],
+            )
+            .allow_if(
+                libc::SYS_prlimit64,
+                vec![ArgCondition::new(0, ArgOp::Eq, SELF_PID)],
+            )
+    }
+
+    /// Allow the process to set its own affinity, as the runtime of Deno does,
+    /// failing with `EPERM` on other processes of the user rather than
+    /// re-pinning them.
+    pub fn allow_own_affinity(self) -> Self {
+        self.allow_if(
+            libc::SYS_sched_setaffinity,
+            vec![ArgCondition::new(0, ArgOp::Eq, 0)],
+        )
+        .allow_if(
+            libc::SYS_sched_setaffinity,
+            vec![ArgCondition::new(0, ArgOp::Eq, SELF_PID)],
+        )
+        .deny_if(
+            libc::SYS_sched_setaffinity,
+            vec![
+                ArgCondition::new(0, ArgOp::Ne, 0),
+                ArgCondition::new(0, ArgOp::Ne, SELF_PID),
+            ],
+            libc::EPERM,
+        )
+    }
+
+    /// The strict allowlist extended with what the runtime of `language` needs.
+    pub fn for_language(language: Language) -> Self {
+        let policy = Self::strict();
+        match language {
+            Language::C | Language::CPP => policy,
+            Language::Rust => policy.allow_all(&[libc::SYS_sigaltstack, libc::SYS_poll]),
+            Language::Python => policy
+                .allow_all(&[libc::SYS_getdents64, libc::SYS_dup])
+                // Name service lookups probe for `nscd`, let them fail gracefully
+                .deny(libc::SYS_socket, libc::EACCES),
+            Language::Golang => policy.allow_threads().allow_all(&[
+                libc::SYS_sigaltstack,
+                libc::SYS_mincore,
+                libc::SYS_epoll_create1,
+                libc::SYS_epoll_ctl,
+                libc::SYS_epoll_pwait,
+                libc::SYS_pipe2,
+            ]),
+            Language::Java => policy
+                .allow_threads()
+                .allow_all(&[
+                    libc::SYS_getdents64,
+                    libc::SYS_prctl,
+                    libc::SYS_dup2,
+                    libc::SYS_mincore,
+                ])
+                // The JVM tunes `/proc/self/coredump_filter` and probes `/dev/null`
+                .deny_writes()
+                .deny(libc::SYS_socket, libc::EACCES),
+            Language::NodeJs => policy.allow_threads().allow_own_affinity().allow_all(&[
+                libc::SYS_sigaltstack,
+                libc::SYS_getdents64,
+                libc::SYS_prctl,
+                libc::SYS_mincore,
+                libc::SYS_poll,
+                libc::SYS_epoll_create1,
+                libc::SYS_epoll_ctl,
+                libc::SYS_epoll_pwait,
+                libc::SYS_epoll_wait,
+                libc::SYS_eventfd2,
+                libc::SYS_pipe2,
+                libc::SYS_memfd_create,
+                libc::SYS_ftruncate,
+            ]),
+        }
+    }
+
+    pub fn allow(self, syscall: i64) -> Self {
+        self.allow_if(syscall, vec![])
+    }
+
+    pub fn allow_all(self, syscalls: &[i64]) -> Self {
+        syscalls
+            .iter()
+            .fold(self, |policy, syscall| policy.allow(*syscall))
+    }
+
+    pub fn allow_if(mut self, syscall: i64, conditions: Vec<ArgCondition>) -> Self {
+        self.allow.push(SyscallRule {
+            syscall,
+            conditions,
+        });
+        self
+    }
+
+    pub fn deny(self, syscall: i64, errno: i32) -> Self {
+        self.deny_if(syscall, vec![], errno)
+    }
+
+    pub fn deny_if(mut self, syscall: i64, conditions: Vec<ArgCondition>, errno: i32) -> Self {
+        self.deny.push(DeniedSyscall {
+            syscall,
+            conditions,
+            errno,
+        });
+        self
+    }
+
+    /// Make opening files for writing fail with `EACCES` instead of killing
+    /// the process, for runtimes which probe writable files on startup.
+    pub fn deny_writes(self) -> Self {
+        let access = libc::O_ACCMODE as u64;
+        [
+            (access, libc::O_WRONLY as u64),
+            (access, libc::O_RDWR as u64),
+            (libc::O_CREAT as u64, libc::O_CREAT as u64),
+            (libc::O_TRUNC as u64, libc::O_TRUNC as u64),
+        ]
+        .into_iter()
+        .fold(self, |policy, (mask, value)| {
+            policy.deny_if(
+                libc::SYS_openat,
+                vec![ArgCondition::new(2, ArgOp::MaskedEq(mask), value)],
+                libc::EACCES,
+            )
+        })
+    }
+
+    /// Allow creating threads, but not new processes.
+    ///
+    /// `clone3` passes its flags in memory where seccomp cannot inspect them,
+    /// so it fails with `ENOSYS` and the C library falls back to `clone`.
+    pub fn allow_threads(self) -> Self {
+        let thread = libc::CLONE_THREAD as u64;
+        self.allow_if(
+            libc::SYS_clone,
+            vec![ArgCondition::new(0, ArgOp::MaskedEq(thread), thread)],
+        )
+        .deny(libc::SYS_clone3, libc::ENOSYS)
+    }
+
+    /// Allow `execve` only of the path at `address` in the memory of the
+    /// process, that is the single `exec` of the submission by the sandbox
+    /// which installed the filters, never another one by the submission.
+    pub fn allow_exec_of(self, address: u64) -> Self {
+        self.allow_if(
+            libc::SYS_execve,
+            vec![ArgCondition::new(0, ArgOp::Eq, address)],
+        )
+    }
+
+    /// Fail the creation of processes and threads with `EAGAIN`, as a
+    /// `RLIMIT_NPROC` of 0 does, for sandboxes which cannot set it.
+    pub fn deny_processes(self) -> Self {
+        [libc::SYS_clone, libc::SYS_fork, libc::SYS_vfork]
+            .into_iter()
+            .fold(self, |policy, syscall| policy.deny(syscall, libc::EAGAIN))
+            .deny(libc::SYS_clone3, libc::ENOSYS)
+    }
+
+    /// Compile the policy into BPF programs, to be installed in order.
+    ///
+    /// The allowlist comes last, since it forbids installing further filters.
+    /// It must be installed with [`install_notifying`]: instead of killing the
+    /// process, rejected system calls are reported to the judge, which learns
+    /// the offending system call before killing it.
+    pub fn compile(&self) -> Result<Filters> {
+        let mut filters = self.compile_trapping()?;
+        // `seccompiler` has no action for user notifications, so rewrite the
+        // traps. System calls of a foreign architecture still kill the process.
+        for instruction in filters.programs.last_mut().into_iter().flatten() {
+            if instruction.code == BPF_RET_K && instruction.k == libc::SECCOMP_RET_TRAP {
+                instruction.k = libc::SECCOMP_RET_USER_NOTIF;
+            }
+        }
+        Ok(filters)
+    }
+
+    /// Compile the policy like [`Self::compile`], but with an allowlist
+    /// stopping a `ptrace` tracer at every system call, with
+    /// [`TRACE_REJECTED`] as data for the ones it rejects.
+    pub fn compile_tracing(&self) -> Result<Filters> {
+        let mut filters = self.compile_trapping()?;
+        for instruction in filters.programs.last_mut().into_iter().flatten() {
+            if instruction.code != BPF_RET_K {
+                continue;
+            }
+            if instruction.k == libc::SECCOMP_RET_TRAP {
+                instruction.k = libc::SECCOMP_RET_TRACE | TRACE_REJECTED;
+            } else if instruction.k == libc::SECCOMP_RET_ALLOW {
+                instruction.k = libc::SECCOMP_RET_TRACE;
+            }
+        }
+        Ok(filters)
+    }
+
+    /// Compile the policy like [`Self::compile`], but with an allowlist
+    /// raising `SIGSYS` on rejected system calls, for sandboxes which install
+    /// the filters themselves and cannot hand a listener back.
+    pub fn compile_trapping(&self) -> Result<Filters> {
+        // The PID is only known in the child, which must not compile anything
+        let [programs, others] = PID_STAND_INS.map(|pid| self.with_pid(pid).programs());
+        let (programs, others) = (programs?, others?);
+        let mut pid_slots = vec![];
+        for (i, (program, other)) in programs.iter().zip(&others).enumerate() {
+            for (j, (instruction, other)) in program.iter().zip(other).enumerate() {
+                if instruction.k != other.k {
+                    pid_slots.push((i, j));
+                }
+            }
+        }
+        Ok(Filters {
+            programs,
+            pid_slots,
+        })
+    }
+
+    /// The policy with [`SELF_PID`] replaced by `pid`.
+    fn with_pid(&self, pid: u64) -> Self {
+        let bind = |conditions: &[ArgCondition]| -> Vec<ArgCondition> {
+            conditions
+                .iter()
+                .map(|condition| match condition.value {
+                    SELF_PID => ArgCondition {
+                        value: pid,
+                        ..*condition
+                    },
+                    _ => *condition,
+                })
+                .collect()
+        };
+        Self {
+            allow: self
+                .allow
+                .iter()
+                .map(|rule| SyscallRule {
+                    syscall: rule.syscall,
+                    conditions: bind(&rule.conditions),
+                })
+                .collect(),
+            deny: self
+                .deny
+                .iter()
+                .map(|denied| DeniedSyscall {
+                    conditions: bind(&denied.conditions),
+                    ..*denied
+                })
+                .collect(),
+        }
+    }
+
+    /// The trapping programs of a policy without [`SELF_PID`].
+    fn programs(&self) -> Result<Vec<BpfProgram>> {
+        let mut filters = vec![];
+        for denied in &self.deny {
+            filters.push(
+                SeccompFilter::new(
+                    compile_rules(&[denied.rule()])?,
+                    SeccompAction::Allow,
+                    SeccompAction::Errno(denied.errno as u32),
+                    TargetArch::x86_64,
+                )?
+                .try_into()?,
+            );
+        }
+
+        // Denied system calls must pass the allowlist to reach their `errno`
+        let mut passed = self.allow.clone();
+        passed.extend(self.deny.iter().map(DeniedSyscall::rule));
+        filters.push(
+            SeccompFilter::new(
+                compile_rules(&passed)?,
+                SeccompAction::Trap,
+                SeccompAction::Allow,
+                TargetArch::x86_64,
+            )?
+            .try_into()?,
+        );
+        Ok(filters)
+    }
+
+    /// Write the policy in Kafel, for `nsjail --seccomp_string`, with
+    /// [`SELF_PID`] standing for `pid` and rejected system calls raising
+    /// `SIGSYS` like [`Self::compile_trapping`].
+    pub fn to_kafel(&self, pid: u32) -> String {
+        let mut actions = vec![];
+        for denied in &self.deny {
+            let condition = kafel_condition(&[&denied.conditions], pid);
+            let rule = kafel_rule(denied.syscall, condition);
+            actions.push(format!("ERRNO({}) {{ {} }}", denied.errno, rule));
+        }
+
+        let mut allowed: BTreeMap<i64, Vec<&[ArgCondition]>> = BTreeMap::new();
+        for rule in &self.allow {
+            allowed
+                .entry(rule.syscall)
+                .or_default()
+                .push(&rule.conditions);
+        }
+        let mut rules = vec![];
+        for (syscall, alternatives) in allowed {
+            let denied: Vec<&[ArgCondition]> = self
+                .deny
+                .iter()
+                .filter(|denied| denied.syscall == syscall)
+                .map(|denied| denied.conditions.as_slice())
+                .collect();
+            let mut condition = kafel_condition(&alternatives, pid);
+            // Kafel does not tell which of overlapping rules applies, so leave
+            // the denied invocations out of the allowlist
+            if !denied.is_empty() {
+                let Some(denied) = kafel_condition(&denied, pid) else {
+                    continue;
+                };
+                condition = Some(match condition {
+                    Some(condition) => format!("({}) && !({})", condition, denied),
+                    None => format!("!({})", denied),
+                });
+            }
+            rules.push(kafel_rule(syscall, condition));
+        }
+        actions.push(format!("ALLOW {{ {} }}", rules.join(", ")));
+        format!(
+            "POLICY eval_stack {{ {} }} USE eval_stack DEFAULT TRAP(0)",
+            actions.join(", ")
+        )
+    }
+}
+
+/// Names given to the arguments of system calls in Kafel.
+const KAFEL_ARGS: [&str; 6] = ["a0", "a1", "a2", "a3", "a4", "a5"];
+
+/// A Kafel rule matching `syscall` when `condition` holds, or always.
+fn kafel_rule(syscall: i64, condition: Option<String>) -> String {
+    match condition {
+        Some(condition) => format!(
+            "SYSCALL[{}]({}) {{ {} }}",
+            syscall,
+            KAFEL_ARGS.join(", "),
+            condition
+        ),
+        None => format!("SYSCALL[{}]", syscall),
+    }
+}
+
+/// A Kafel expression holding when all conditions of any of the
+/// `alternatives` hold, `None` when one of them has no condition.
+fn kafel_condition(alternatives: &[&[ArgCondition]], pid: u32) -> Option<String> {
+    if alternatives.iter().any(|conditions| conditions.is_empty()) {
+        return None;
+    }
+    let alternatives: Vec<String> = alternatives
+        .iter()
+        .map(|conditions| {
+            let all: Vec<String> = conditions.iter().map(|c| c.kafel(pid)).collect();
+            format!("({})", all.join(" && "))
+        })
+        .collect();
+    Some(alternatives.join(" || "))
+}
+
+impl DeniedSyscall {
+    fn rule(&self) -> SyscallRule {
+        SyscallRule {
+            syscall: self.syscall,
+            conditions: self.conditions.clone(),
+        }
+    }
+}
+
+impl ArgCondition {
+    fn kafel(self, pid: u32) -> String {
+        let value = match self.value {
+            SELF_PID => pid as u64,
+            value => value,
+        };
+        let name = KAFEL_ARGS[self.arg as usize];
+        // Compared on the lower half only when `compile` compares a `Dword`
+        let arg = match self.op {
+            ArgOp::MaskedEq(mask) => format!("({} & {:#x})", name, mask),
+            _ if self.value > u32::MAX as u64 => name.to_string(),
+            _ => format!("({} & 0xffffffff)", name),
+        };
+        let op = match self.op {
+            ArgOp::Eq | ArgOp::MaskedEq(_) => "==",
+            ArgOp::Ne => "!=",
+            ArgOp::Lt => "<",
+            ArgOp::Le => "<=",
+            ArgOp::Gt => ">",
+            ArgOp::Ge => ">=",
+        };
+        format!("{} {} {:#x}", arg, op, value)
+    }
+
+    fn compile(&self) -> Result<SeccompCondition> {
+        let op = match self.op {
+            ArgOp::Eq => SeccompCmpOp::Eq,
+            ArgOp::Ne => SeccompCmpOp::Ne,
+            ArgOp::Lt => SeccompCmpOp::Lt,
+            ArgOp::Le => SeccompCmpOp::Le,
+            ArgOp::Gt => SeccompCmpOp::Gt,
+            ArgOp::Ge => SeccompCmpOp::Ge,
+            ArgOp::MaskedEq(mask) => SeccompCmpOp::MaskedEq(mask),
+        };
+        // Most arguments are C `int`s, whose upper half is not guaranteed to be zero
+        let len = match self.op {
+            ArgOp::MaskedEq(mask) if mask > u32::MAX as u64 => SeccompCmpArgLen::Qword,
+            _ if self.value > u32::MAX as u64 => SeccompCmpArgLen::Qword,
+            _ => SeccompCmpArgLen::Dword,
+        };
+        Ok(SeccompCondition::new(self.arg, len, op, self.value)?)
+    }
+}
+
+/// Group rules by system call. An empty rule list matches every invocation.
+fn compile_rules(rules: &[SyscallRule]) -> Result<BTreeMap<i64, Vec<SeccompRule>>> {
+    let mut compiled: BTreeMap<i64, Vec<SeccompRule>> = BTreeMap::new();
+    for rule in rules {
+        let entry = compiled.entry(rule.syscall).or_default();
+        let unconditional = rules
+            .iter()
+            .any(|other| other.syscall == rule.syscall && other.conditions.is_empty());
+        if !unconditional {
+            entry.push(SeccompRule::new(
+                rule.conditions
+                    .iter()
+                    .map(ArgCondition::compile)
+                    .collect::<Result<_>>()?,
+            )?);
+        }
+    }
+    Ok(compiled)
+}
+
+/// BPF programs compiled from a [`SeccompPolicy`], to be installed in order,
+/// and where they compare with the PID of the process.
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    pub programs: Vec<BpfProgram>,
+    /// Instructions comparing with [`SELF_PID`], by program and index.
+    pid_slots: Vec<(usize, usize)>,
+}
+
+impl Filters {
+    /// Compare with the PID of the caller where the policy says
+    /// [`SELF_PID`], as seen from its PID namespace.
+    ///
+    /// Only issues `getpid`, so it may be called in a forked child.
+    pub fn bind_pid(&mut self) {
+        self.bind_pid_to(unsafe { libc::getpid() } as u32);
+    }
+
+    /// Compare with a known `pid` where the policy says [`SELF_PID`].
+    pub fn bind_pid_to(&mut self, pid: u32) {
+        for &(program, instruction) in &self.pid_slots {
+            self.programs[program][instruction].k = pid;
+        }
+    }
+}
+
+/// Install `filter`, unlike `seccompiler::apply_filter` without allocating.
+///
+/// # Safety
+///
+/// Must only be called in a forked child right before `exec`.
+pub unsafe fn install(filter: &BpfProgram) -> io::Result<()> {
+    seccomp(filter, 0).map(drop)
+}
+
+/// Install `filter` and return the listener notified of the system calls it
+/// rejects.
+///
+/// # Safety
+///
+/// Must only be called in a forked child right before `exec`.
+pub unsafe fn install_notifying(filter: &BpfProgram) -> io::Result<RawFd> {
+    seccomp(filter, libc::SECCOMP_FILTER_FLAG_NEW_LISTENER)
+}
+
+unsafe fn seccomp(filter: &BpfProgram, flags: libc::c_ulong) -> io::Result<RawFd> {
+    let prog = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr() as *mut libc::sock_filter,
+    };
+    let fd = libc::syscall(
+        libc::SYS_seccomp,
+        libc::SECCOMP_SET_MODE_FILTER,
+        flags,
+        &prog,
+    );
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd as RawFd)
+}
+
+enum ListenerState {
+    /// Waiting for the child to send the listener over the socket.
+    Connecting(AsyncFd<OwnedFd>),
+    Listening(AsyncFd<OwnedFd>),
+    Closed,
+}
+
+/// Receives the system calls rejected by the allowlist of a running child.
+pub struct ViolationListener {
+    state: ListenerState,
+}
+
+impl ViolationListener {
+    /// Create the listener and the socket end to hand to the child.
+    pub fn new() -> io::Result<(Self, OwnedFd)> {
+        let mut fds = [0; 2];
+        if unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let (parent, child) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        set_nonblocking(&parent)?;
+        let state = ListenerState::Connecting(AsyncFd::with_interest(parent, Interest::READABLE)?);
+        Ok((Self { state }, child))
+    }
+
+    /// Poll for a rejected system call, returning its number.
+    pub fn poll_violation(&mut self, cx: &mut Context<'_>) -> Option<i64> {
+        loop {
+            match &self.state {
+                ListenerState::Connecting(socket) => {
+                    let Poll::Ready(Ok(mut guard)) = socket.poll_read_ready(cx) else {
+                        return None;
+                    };
+                    self.state = match recv_fd(socket.as_raw_fd()) {
+                        Ok(Some(fd)) => match AsyncFd::with_interest(fd, Interest::READABLE) {
+                            Ok(listener) => ListenerState::Listening(listener),
+                            Err(_) => ListenerState::Closed,
+                        },
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            guard.clear_ready();
+                            continue;
+                        }
+                        // The child exited or failed before installing its filters
+                        _ => ListenerState::Closed,
+                    };
+                }
+                ListenerState::Listening(listener) => {
+                    let Poll::Ready(Ok(mut guard)) = listener.poll_read_ready(cx) else {
+                        return None;
+                    };
+                    // Every task using the filter has exited. Receiving would
+                    // block forever, as the ioctl ignores `O_NONBLOCK`.
+                    if guard.ready().is_read_closed() {
+                        self.state = ListenerState::Closed;
+                        continue;
+                    }
+                    let mut notif: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+                    let ret = unsafe {
+                        libc::ioctl(listener.as_raw_fd(), SECCOMP_IOCTL_NOTIF_RECV, &mut notif)
+                    };
+                    if ret == 0 {
+                        return Some(notif.data.nr as i64);
+                    }
+                    match io::Error::last_os_error().raw_os_error() {
+                        // The notifying task died before we could receive it
+                        Some(libc::ENOENT) | Some(libc::EINTR) => guard.clear_ready(),
+                        _ => self.state = ListenerState::Closed,
+                    }
+                }
+                ListenerState::Closed => return None,
+            }
+        }
+    }
+}
+
+fn set_nonblocking(fd: &OwnedFd) -> io::Result<()> {
+    let fd = fd.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
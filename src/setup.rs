@@ -0,0 +1,279 @@
+use std::{
+    fmt, io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
+
+/// A step of the sandbox setup performed in the forked child before `exec`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SetupStep {
+    JoinCgroup = 1,
+    NoNewPrivileges,
+    UnshareMount,
+    UnshareUser,
+    WriteSetgroups,
+    WriteUidMap,
+    WriteGidMap,
+    UnshareNamespaces,
+    Fork,
+    ParentDeathSignal,
+    MakeMountsPrivate,
+    MountRoot,
+    CreateDirectory,
+    CreateFile,
+    BindMount,
+    StatMount,
+    RemountReadOnly,
+    CreateSymlink,
+    MountProc,
+    PivotRoot,
+    DetachOldRoot,
+    ChangeDirectory,
+    MemoryLimit,
+    SeccompFilter,
+    SeccompListener,
+    SendListener,
+    ResourceLimit,
+    SetGroups,
+    SetGid,
+    SetUid,
+    SetDumpable,
+    UnshareNetwork,
+    TraceMe,
+    SetAffinity,
+    OpenCounter,
+    SendCounter,
+    Spawn,
+}
+
+const STEPS: &[SetupStep] = &[
+    SetupStep::JoinCgroup,
+    SetupStep::NoNewPrivileges,
+    SetupStep::UnshareMount,
+    SetupStep::UnshareUser,
+    SetupStep::WriteSetgroups,
+    SetupStep::WriteUidMap,
+    SetupStep::WriteGidMap,
+    SetupStep::UnshareNamespaces,
+    SetupStep::Fork,
+    SetupStep::ParentDeathSignal,
+    SetupStep::MakeMountsPrivate,
+    SetupStep::MountRoot,
+    SetupStep::CreateDirectory,
+    SetupStep::CreateFile,
+    SetupStep::BindMount,
+    SetupStep::StatMount,
+    SetupStep::RemountReadOnly,
+    SetupStep::CreateSymlink,
+    SetupStep::MountProc,
+    SetupStep::PivotRoot,
+    SetupStep::DetachOldRoot,
+    SetupStep::ChangeDirectory,
+    SetupStep::MemoryLimit,
+    SetupStep::SeccompFilter,
+    SetupStep::SeccompListener,
+    SetupStep::SendListener,
+    SetupStep::ResourceLimit,
+    SetupStep::SetGroups,
+    SetupStep::SetGid,
+    SetupStep::SetUid,
+    SetupStep::SetDumpable,
+    SetupStep::UnshareNetwork,
+    SetupStep::TraceMe,
+    SetupStep::SetAffinity,
+    SetupStep::OpenCounter,
+    SetupStep::SendCounter,
+    SetupStep::Spawn,
+];
+
+/// A failed setup step and the `errno` it failed with.
+///
+/// Written by the child over a pipe as three native-endian 32-bit words, so
+/// that reporting it never allocates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetupError {
+    pub step: SetupStep,
+    /// Step specific detail, such as the resource of [`SetupStep::ResourceLimit`].
+    pub detail: u32,
+    pub errno: i32,
+}
+
+impl SetupError {
+    pub fn new(step: SetupStep, errno: i32) -> Self {
+        Self {
+            step,
+            detail: 0,
+            errno,
+        }
+    }
+
+    /// The error of the last failed system call.
+    pub fn last(step: SetupStep) -> Self {
+        Self::new(step, errno())
+    }
+
+    pub fn io(step: SetupStep, error: io::Error) -> Self {
+        Self::new(step, error.raw_os_error().unwrap_or(0))
+    }
+
+    pub fn detail(mut self, detail: u32) -> Self {
+        self.detail = detail;
+        self
+    }
+
+    /// Write the error to the pipe of [`SetupErrors`] and exit the child.
+    ///
+    /// Never returns to `Command::spawn`, whose own error pipe the setup may
+    /// have closed along with every other inherited descriptor: the spawn
+    /// succeeds and the error is read once the child exited.
+    pub fn exit(self, fd: RawFd) -> ! {
+        let words = [self.step as u32, self.detail, self.errno as u32];
+        unsafe {
+            libc::write(fd, words.as_ptr().cast(), size_of_val(&words));
+            libc::_exit(127)
+        }
+    }
+
+    fn decode(words: [u32; 3]) -> Option<Self> {
+        let step = *STEPS.iter().find(|step| **step as u32 == words[0])?;
+        Some(Self {
+            step,
+            detail: words[1],
+            errno: words[2] as i32,
+        })
+    }
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let call = match self.step {
+            SetupStep::JoinCgroup => "write(cgroup.procs)",
+            SetupStep::NoNewPrivileges => "prctl(PR_SET_NO_NEW_PRIVS)",
+            SetupStep::UnshareMount => "unshare(CLONE_NEWNS)",
+            SetupStep::UnshareUser => "unshare(CLONE_NEWUSER)",
+            SetupStep::UnshareNetwork => "unshare(CLONE_NEWNET)",
+            SetupStep::WriteSetgroups => "write(/proc/self/setgroups)",
+            SetupStep::WriteUidMap => "write(/proc/self/uid_map)",
+            SetupStep::WriteGidMap => "write(/proc/self/gid_map)",
+            SetupStep::UnshareNamespaces => {
+                "unshare(CLONE_NEWNS | CLONE_NEWPID | CLONE_NEWNET | CLONE_NEWIPC | CLONE_NEWUTS)"
+            }
+            SetupStep::Fork => "fork()",
+            SetupStep::ParentDeathSignal => "prctl(PR_SET_PDEATHSIG)",
+            SetupStep::MakeMountsPrivate => "mount(MS_REC | MS_PRIVATE)",
+            SetupStep::MountRoot => "mount(tmpfs)",
+            SetupStep::CreateDirectory => "mkdir",
+            SetupStep::CreateFile => "open(O_CREAT)",
+            SetupStep::BindMount => "mount(MS_BIND)",
+            SetupStep::StatMount => "statvfs",
+            SetupStep::RemountReadOnly => "mount(MS_REMOUNT | MS_RDONLY)",
+            SetupStep::CreateSymlink => "symlink",
+            SetupStep::MountProc => "mount(proc)",
+            SetupStep::PivotRoot => "pivot_root",
+            SetupStep::DetachOldRoot => "umount2(MNT_DETACH)",
+            SetupStep::ChangeDirectory => "chdir",
+            SetupStep::MemoryLimit => "setrlimit(RLIMIT_AS)",
+            SetupStep::SeccompFilter => "seccomp(SECCOMP_SET_MODE_FILTER)",
+            SetupStep::SeccompListener => "seccomp(SECCOMP_FILTER_FLAG_NEW_LISTENER)",
+            SetupStep::SendListener => "sendmsg(SCM_RIGHTS)",
+            SetupStep::SetGroups => "setgroups",
+            SetupStep::SetGid => "setgid",
+            SetupStep::SetUid => "setuid",
+            SetupStep::SetDumpable => "prctl(PR_SET_DUMPABLE)",
+            SetupStep::TraceMe => "ptrace(PTRACE_TRACEME)",
+            SetupStep::SetAffinity => "sched_setaffinity",
+            SetupStep::OpenCounter => "perf_event_open",
+            SetupStep::SendCounter => "sendmsg(SCM_RIGHTS)",
+            SetupStep::Spawn => "fork/execve",
+            SetupStep::ResourceLimit => {
+                return write!(
+                    f,
+                    "setrlimit({}) failed: {}",
+                    rlimit_name(self.detail),
+                    errno_name(self.errno)
+                )
+            }
+        };
+        write!(f, "{} failed: {}", call, errno_name(self.errno))
+    }
+}
+
+impl std::error::Error for SetupError {}
+
+/// Receiving end of the CLOEXEC pipe over which the child reports setup
+/// failures. It stays empty when the child reaches `exec`.
+pub struct SetupErrors {
+    pipe: OwnedFd,
+}
+
+impl SetupErrors {
+    /// Create the pipe, returning the write end to hand to the child.
+    pub fn new() -> io::Result<(Self, OwnedFd)> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        Ok((Self { pipe: read }, write))
+    }
+
+    /// The failure reported by the child, if any. Never blocks.
+    pub fn read(&self) -> Option<SetupError> {
+        let mut words = [0u32; 3];
+        let len = size_of_val(&words);
+        let read = unsafe { libc::read(self.pipe.as_raw_fd(), words.as_mut_ptr().cast(), len) };
+        (read == len as isize).then(|| SetupError::decode(words))?
+    }
+}
+
+/// Turn the return value of a system call into a [`SetupError`] on failure.
+pub fn check(step: SetupStep, ret: libc::c_int) -> Result<libc::c_int, SetupError> {
+    if ret < 0 {
+        return Err(SetupError::last(step));
+    }
+    Ok(ret)
+}
+
+fn errno() -> i32 {
+    unsafe { *libc::__errno_location() }
+}
+
+fn errno_name(errno: i32) -> String {
+    let name = match errno {
+        libc::EPERM => "EPERM",
+        libc::ENOENT => "ENOENT",
+        libc::EINTR => "EINTR",
+        libc::EIO => "EIO",
+        libc::EBADF => "EBADF",
+        libc::EAGAIN => "EAGAIN",
+        libc::ENOMEM => "ENOMEM",
+        libc::EACCES => "EACCES",
+        libc::EFAULT => "EFAULT",
+        libc::EBUSY => "EBUSY",
+        libc::EEXIST => "EEXIST",
+        libc::ENOTDIR => "ENOTDIR",
+        libc::EINVAL => "EINVAL",
+        libc::ENFILE => "ENFILE",
+        libc::EMFILE => "EMFILE",
+        libc::ENOSPC => "ENOSPC",
+        libc::EROFS => "EROFS",
+        libc::ENOSYS => "ENOSYS",
+        libc::EUSERS => "EUSERS",
+        _ => return format!("errno {}", errno),
+    };
+    name.to_string()
+}
+
+fn rlimit_name(resource: u32) -> String {
+    let name = match resource {
+        libc::RLIMIT_AS => "RLIMIT_AS",
+        libc::RLIMIT_CORE => "RLIMIT_CORE",
+        libc::RLIMIT_CPU => "RLIMIT_CPU",
+        libc::RLIMIT_FSIZE => "RLIMIT_FSIZE",
+        libc::RLIMIT_NOFILE => "RLIMIT_NOFILE",
+        libc::RLIMIT_NPROC => "RLIMIT_NPROC",
+        libc::RLIMIT_STACK => "RLIMIT_STACK",
+        _ => return format!("resource {}", resource),
+    };
+    name.to_string()
+}
@@ -0,0 +1,239 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::{
+    case::Prepared,
+    compile::Language,
+    config::{JudgeOptions, TestCase},
+    judge::{JudgeResult, JudgeStatus},
+    sandbox::Sandbox,
+};
+
+/// A named group of test cases scored together.
+#[derive(Debug, Clone)]
+pub struct Subtask {
+    pub name: String,
+    pub test_cases: Vec<TestCase<PathBuf, PathBuf>>,
+    pub scoring: Scoring,
+    /// Names of the subtasks which must be accepted for this one to run,
+    /// declared before it.
+    pub dependencies: Vec<String>,
+    /// Stop at the first test case earning no points, the rest earning none
+    /// either. On by default with [`Scoring::Min`], where it cannot change
+    /// the score.
+    pub fail_fast: bool,
+}
+
+/// How the score of a subtask is made from the scores of its test cases.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case", tag = "type"))]
+pub enum Scoring {
+    /// `score` times the lowest fraction earned by a test case, as in IOI
+    /// subtasks. Test case weights are ignored.
+    Min { score: f64 },
+    /// Sum of the weighted scores of the test cases, as in OI contests.
+    Sum,
+}
+
+/// Results of `run_subtasks`, one per subtask.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubtaskResults {
+    /// Verdict of the first test case not accepted, or of the compilation.
+    pub status: JudgeStatus,
+    /// Sum of the scores of the subtasks.
+    pub score: f64,
+    /// Empty when the submission failed to compile.
+    pub subtasks: Vec<SubtaskResult>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubtaskResult {
+    pub name: String,
+    pub status: SubtaskStatus,
+    pub score: f64,
+    /// One per test case run, in order.
+    pub results: Vec<JudgeResult>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case", tag = "type"))]
+pub enum SubtaskStatus {
+    /// Every test case was accepted.
+    Accepted,
+    /// Some points were earned, short of the full score.
+    PartiallyAccepted,
+    /// No points were earned.
+    Failed {
+        /// Verdict of the first test case not accepted.
+        status: JudgeStatus,
+    },
+    /// Not run, as a subtask it depends on was not accepted.
+    Skipped { dependency: String },
+}
+
+impl Subtask {
+    pub fn new<S: Into<String>>(name: S, scoring: Scoring) -> Self {
+        Self {
+            name: name.into(),
+            test_cases: vec![],
+            scoring,
+            dependencies: vec![],
+            fail_fast: matches!(scoring, Scoring::Min { .. }),
+        }
+    }
+
+    pub fn test_case<C: Into<TestCase<PathBuf, PathBuf>>>(mut self, test_case: C) -> Self {
+        self.test_cases.push(test_case.into());
+        self
+    }
+
+    pub fn depends_on<S: Into<String>>(mut self, name: S) -> Self {
+        self.dependencies.push(name.into());
+        self
+    }
+
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+}
+
+impl SubtaskResult {
+    fn new(name: String, scoring: Scoring, results: Vec<JudgeResult>) -> Self {
+        let score = match scoring {
+            Scoring::Min { score } => {
+                let points = results
+                    .iter()
+                    .map(|result| result.status.points())
+                    .fold(1.0, f64::min);
+                score * points
+            }
+            Scoring::Sum => results.iter().map(|result| result.score).sum(),
+        };
+        let status = match results.iter().find(|result| !result.is_accepted()) {
+            None => SubtaskStatus::Accepted,
+            Some(_) if score > 0.0 => SubtaskStatus::PartiallyAccepted,
+            Some(failed) => SubtaskStatus::Failed {
+                status: failed.status.clone(),
+            },
+        };
+        Self {
+            name,
+            status,
+            score,
+            results,
+        }
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        matches!(self.status, SubtaskStatus::Accepted)
+    }
+}
+
+/// Run the test cases of `subtasks` in order, like `run_test_cases`, with the
+/// fail-fast of each subtask rather than [`JudgeOptions::fail_fast`].
+///
+/// Fails before compiling when a subtask has no test case, which would
+/// otherwise earn its full score, or depends on one which is not declared
+/// before it.
+pub async fn run_subtasks<B>(
+    language: Language,
+    workspace: B,
+    source_file_path: B,
+    options: JudgeOptions,
+    sandbox: &dyn Sandbox,
+    subtasks: Vec<Subtask>,
+    clean: bool,
+) -> Result<SubtaskResults>
+where
+    B: Into<PathBuf>,
+{
+    for (i, subtask) in subtasks.iter().enumerate() {
+        if subtask.test_cases.is_empty() {
+            anyhow::bail!("Subtask {} has no test case", subtask.name);
+        }
+        for dependency in &subtask.dependencies {
+            if !subtasks[..i].iter().any(|s| &s.name == dependency) {
+                anyhow::bail!(
+                    "Subtask {} depends on {}, which is not declared before it",
+                    subtask.name,
+                    dependency
+                );
+            }
+        }
+    }
+
+    let prepared = match Prepared::new(
+        language,
+        workspace.into(),
+        source_file_path.into(),
+        options,
+        sandbox,
+        clean,
+    )
+    .await?
+    {
+        Ok(prepared) => prepared,
+        Err(compile_error) => {
+            return Ok(SubtaskResults {
+                status: compile_error.status,
+                score: 0.0,
+                subtasks: vec![],
+            })
+        }
+    };
+
+    let mut results = SubtaskResults {
+        status: JudgeStatus::Accepted,
+        score: 0.0,
+        subtasks: vec![],
+    };
+    for subtask in subtasks {
+        let failed_dependency = subtask.dependencies.iter().find(|dependency| {
+            results
+                .subtasks
+                .iter()
+                .any(|result| &result.name == *dependency && !result.is_accepted())
+        });
+        if let Some(dependency) = failed_dependency {
+            results.subtasks.push(SubtaskResult {
+                name: subtask.name,
+                status: SubtaskStatus::Skipped {
+                    dependency: dependency.clone(),
+                },
+                score: 0.0,
+                results: vec![],
+            });
+            continue;
+        }
+
+        let mut test_results = vec![];
+        for test_case in subtask.test_cases {
+            let result = match prepared.run(test_case).await {
+                Ok(result) => result,
+                Err(e) => return Err(prepared.abandon(clean, e).await),
+            };
+            let earned_nothing = result.status.points() == 0.0;
+            test_results.push(result);
+            if subtask.fail_fast && earned_nothing {
+                break;
+            }
+        }
+        let result = SubtaskResult::new(subtask.name, subtask.scoring, test_results);
+        if let Some(failed) = result.results.iter().find(|r| !r.is_accepted()) {
+            if matches!(results.status, JudgeStatus::Accepted) {
+                results.status = failed.status.clone();
+            }
+        }
+        results.score += result.score;
+        results.subtasks.push(result);
+    }
+
+    prepared.finish(clean).await?;
+    Ok(results)
+}
@@ -0,0 +1,262 @@
+use std::{
+    collections::VecDeque,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    task::{Context, Poll},
+};
+
+use tokio::io::{unix::AsyncFd, Interest};
+
+use crate::setup::{check, SetupError, SetupStep};
+
+/// `SECCOMP_RET_TRACE` data of system calls rejected by the allowlist.
+pub const TRACE_REJECTED: u32 = 1;
+
+/// Native-endian words of a record sent by the tracer: the system call
+/// number, its six arguments and the `SECCOMP_RET_TRACE` data.
+const RECORD_WORDS: usize = 8;
+const RECORD_LEN: usize = RECORD_WORDS * size_of::<u64>();
+
+/// A system call made by a traced submission.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TracedSyscall {
+    pub nr: i64,
+    pub args: [u64; 6],
+    /// Rejected by the seccomp policy, which stopped the run.
+    pub rejected: bool,
+}
+
+impl TracedSyscall {
+    fn decode(record: &[u8]) -> Self {
+        let word = |i: usize| {
+            let bytes = &record[i * size_of::<u64>()..(i + 1) * size_of::<u64>()];
+            u64::from_ne_bytes(bytes.try_into().unwrap())
+        };
+        Self {
+            nr: word(0) as i64,
+            args: std::array::from_fn(|i| word(i + 1)),
+            rejected: word(7) == TRACE_REJECTED as u64,
+        }
+    }
+}
+
+/// The last system calls of a traced run, streamed by the tracer over a pipe.
+///
+/// Draining while the child runs keeps the tracer, and so the child, from
+/// blocking on a full pipe.
+pub struct SyscallTrace {
+    pipe: AsyncFd<OwnedFd>,
+    partial: Vec<u8>,
+    syscalls: VecDeque<TracedSyscall>,
+    limit: usize,
+}
+
+impl SyscallTrace {
+    /// Create the pipe keeping the last `limit` system calls, returning the
+    /// write end to hand to the tracer.
+    pub fn new(limit: usize) -> io::Result<(Self, OwnedFd)> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        // Only our end is non-blocking, the tracer waits for us to catch up
+        unsafe {
+            let flags = libc::fcntl(read.as_raw_fd(), libc::F_GETFL);
+            if flags < 0
+                || libc::fcntl(read.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let trace = Self {
+            pipe: AsyncFd::with_interest(read, Interest::READABLE)?,
+            partial: vec![],
+            syscalls: VecDeque::with_capacity(limit),
+            limit,
+        };
+        Ok((trace, write))
+    }
+
+    /// Drain the pipe whenever it becomes readable.
+    pub fn poll_drain(&mut self, cx: &mut Context<'_>) {
+        while let Poll::Ready(Ok(mut guard)) = self.pipe.poll_read_ready_mut(cx) {
+            match read(guard.get_inner().as_raw_fd()) {
+                Ok(Some(chunk)) => self.push(&chunk),
+                Ok(None) => return,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    /// Read everything left once the child has exited.
+    pub fn drain(&mut self) {
+        loop {
+            match read(self.pipe.get_ref().as_raw_fd()) {
+                Ok(Some(chunk)) => self.push(&chunk),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                _ => break,
+            }
+        }
+    }
+
+    /// The traced system calls, oldest first.
+    pub fn syscalls(&self) -> Vec<TracedSyscall> {
+        self.syscalls.iter().copied().collect()
+    }
+
+    /// The system call the seccomp policy stopped the run at, if any.
+    pub fn rejected(&self) -> Option<i64> {
+        self.syscalls
+            .back()
+            .filter(|syscall| syscall.rejected)
+            .map(|syscall| syscall.nr)
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        self.partial.extend_from_slice(chunk);
+        let complete = self.partial.len() / RECORD_LEN * RECORD_LEN;
+        for record in self.partial[..complete].chunks_exact(RECORD_LEN) {
+            if self.syscalls.len() == self.limit {
+                self.syscalls.pop_front();
+            }
+            if self.limit > 0 {
+                self.syscalls.push_back(TracedSyscall::decode(record));
+            }
+        }
+        self.partial.drain(..complete);
+    }
+}
+
+/// Read a chunk of records, `None` at the end of the stream.
+fn read(fd: RawFd) -> io::Result<Option<Vec<u8>>> {
+    let mut chunk = vec![0; 64 * RECORD_LEN];
+    let read = unsafe { libc::read(fd, chunk.as_mut_ptr().cast(), chunk.len()) };
+    match read {
+        n if n < 0 => Err(io::Error::last_os_error()),
+        0 => Ok(None),
+        n => {
+            chunk.truncate(n as usize);
+            Ok(Some(chunk))
+        }
+    }
+}
+
+/// Fork into a tracer and a tracee, returning only in the tracee, which stops
+/// until the tracer is attached.
+///
+/// The tracer writes every system call stopped at by a `SECCOMP_RET_TRACE`
+/// filter to `fd`, kills the tracee on one rejected with [`TRACE_REJECTED`],
+/// and exits with the status of the tracee as `128 + signal` or its exit code.
+/// A rejection is reported as `SIGSYS`.
+///
+/// # Safety
+///
+/// Must only be called in a single-threaded child right before `exec`.
+pub unsafe fn fork_traced(fd: RawFd) -> Result<(), SetupError> {
+    let pid = check(SetupStep::Fork, libc::fork())?;
+    if pid > 0 {
+        trace(pid, fd);
+    }
+    libc::close(fd);
+    check(
+        SetupStep::TraceMe,
+        libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0) as libc::c_int,
+    )?;
+    // Filters installed before the tracer sets its options would fail every call
+    libc::kill(libc::getpid(), libc::SIGSTOP);
+    Ok(())
+}
+
+unsafe fn trace(pid: libc::pid_t, fd: RawFd) -> ! {
+    let mut status = 0;
+    if libc::waitpid(pid, &mut status, libc::__WALL) < 0 || !libc::WIFSTOPPED(status) {
+        libc::_exit(127);
+    }
+    // Follow threads and children, which the filters also apply to
+    let options = libc::PTRACE_O_TRACESECCOMP
+        | libc::PTRACE_O_EXITKILL
+        | libc::PTRACE_O_TRACEEXEC
+        | libc::PTRACE_O_TRACECLONE
+        | libc::PTRACE_O_TRACEFORK
+        | libc::PTRACE_O_TRACEVFORK;
+    libc::ptrace(libc::PTRACE_SETOPTIONS, pid, 0, options);
+    libc::ptrace(libc::PTRACE_CONT, pid, 0, 0);
+
+    let mut rejected = false;
+    loop {
+        let tid = libc::waitpid(-1, &mut status, libc::__WALL);
+        if tid < 0 {
+            if *libc::__errno_location() == libc::EINTR {
+                continue;
+            }
+            libc::_exit(127);
+        }
+        if libc::WIFEXITED(status) || libc::WIFSIGNALED(status) {
+            if tid != pid {
+                continue;
+            }
+            if rejected {
+                libc::_exit(128 + libc::SIGSYS);
+            }
+            if libc::WIFSIGNALED(status) {
+                libc::_exit(128 + libc::WTERMSIG(status));
+            }
+            libc::_exit(libc::WEXITSTATUS(status));
+        }
+        if !libc::WIFSTOPPED(status) {
+            continue;
+        }
+        let signal = match status >> 8 {
+            event if event == libc::SIGTRAP | (libc::PTRACE_EVENT_SECCOMP << 8) => {
+                if record(tid, fd) {
+                    rejected = true;
+                    libc::kill(pid, libc::SIGKILL);
+                }
+                0
+            }
+            // Other ptrace events, such as a new thread or `exec`
+            event if event >> 8 != 0 => 0,
+            // New tracees start stopped
+            _ if libc::WSTOPSIG(status) == libc::SIGSTOP => 0,
+            _ => libc::WSTOPSIG(status),
+        };
+        libc::ptrace(libc::PTRACE_CONT, tid, 0, signal);
+    }
+}
+
+/// Send the system call `tid` is stopped at, returning whether it was rejected.
+unsafe fn record(tid: libc::pid_t, fd: RawFd) -> bool {
+    let mut data: libc::c_ulong = 0;
+    let mut regs = std::mem::zeroed::<libc::user_regs_struct>();
+    libc::ptrace(libc::PTRACE_GETEVENTMSG, tid, 0, &mut data);
+    if libc::ptrace(libc::PTRACE_GETREGS, tid, 0, &mut regs) < 0 {
+        return false;
+    }
+    let words: [u64; RECORD_WORDS] = [
+        regs.orig_rax,
+        regs.rdi,
+        regs.rsi,
+        regs.rdx,
+        regs.r10,
+        regs.r8,
+        regs.r9,
+        data,
+    ];
+    let mut written = 0;
+    while written < RECORD_LEN {
+        let n = libc::write(
+            fd,
+            words.as_ptr().cast::<u8>().add(written).cast(),
+            RECORD_LEN - written,
+        );
+        if n <= 0 {
+            break;
+        }
+        written += n as usize;
+    }
+    data == TRACE_REJECTED as libc::c_ulong
+}
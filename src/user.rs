@@ -0,0 +1,138 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    os::unix::fs::{lchown, PermissionsExt},
+    path::Path,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use anyhow::Result;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{
+    config::RunAs,
+    setup::{check, SetupError, SetupStep},
+};
+
+/// Uids of [`RunAs::Pool`] currently leased by a run.
+static LEASED: LazyLock<Mutex<HashSet<u32>>> = LazyLock::new(Default::default);
+
+/// A permit per uid of each [`RunAs::Pool`], for runs to queue on.
+static POOLS: LazyLock<Mutex<HashMap<Range<u32>, Arc<Semaphore>>>> =
+    LazyLock::new(Default::default);
+
+/// The user and group a submission runs as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Credentials taken from a [`RunAs`], returning a pooled uid on drop.
+#[derive(Debug)]
+pub struct UidLease {
+    pub credentials: Credentials,
+    pooled: bool,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl RunAs {
+    /// Take the credentials to run as, waiting for a free uid of a pool.
+    pub async fn acquire(&self) -> Result<UidLease> {
+        match self {
+            RunAs::User { uid, gid } => Ok(UidLease {
+                credentials: Credentials {
+                    uid: *uid,
+                    gid: *gid,
+                },
+                pooled: false,
+                _permit: None,
+            }),
+            RunAs::Pool { uids } => {
+                if uids.is_empty() {
+                    anyhow::bail!("No uid in {}..{}", uids.start, uids.end);
+                }
+                let free = POOLS
+                    .lock()
+                    .unwrap()
+                    .entry(uids.clone())
+                    .or_insert_with(|| Arc::new(Semaphore::new(uids.len())))
+                    .clone();
+                let permit = free
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore is never closed");
+                let mut leased = LEASED.lock().unwrap();
+                // Only when another pool overlaps this one
+                let Some(uid) = uids.clone().find(|uid| !leased.contains(uid)) else {
+                    anyhow::bail!("No free uid left in {}..{}", uids.start, uids.end);
+                };
+                leased.insert(uid);
+                Ok(UidLease {
+                    credentials: Credentials { uid, gid: uid },
+                    pooled: true,
+                    _permit: Some(permit),
+                })
+            }
+        }
+    }
+}
+
+impl Drop for UidLease {
+    fn drop(&mut self) {
+        if self.pooled {
+            LEASED.lock().unwrap().remove(&self.credentials.uid);
+        }
+    }
+}
+
+impl Credentials {
+    /// Drop the supplementary groups, then switch to the group and user.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called in a single-threaded child right before `exec`.
+    pub unsafe fn switch(&self) -> Result<(), SetupError> {
+        check(SetupStep::SetGroups, libc::setgroups(0, std::ptr::null()))?;
+        check(SetupStep::SetGid, libc::setgid(self.gid))?;
+        check(SetupStep::SetUid, libc::setuid(self.uid))?;
+        // Changing the user clears the dumpable flag, which would leave
+        // `/proc/self` owned by root until `exec`
+        check(
+            SetupStep::SetDumpable,
+            libc::prctl(libc::PR_SET_DUMPABLE, 1, 0, 0, 0),
+        )?;
+        Ok(())
+    }
+
+    /// Let the submission's group read the `workspace` and run what it
+    /// holds, keeping it owned by the judge and unreachable by others, so
+    /// that runs under other uids cannot read it and the submission cannot
+    /// tamper with it.
+    ///
+    /// Nothing is writable by the submission, which writes its output
+    /// through the descriptor the judge passes it.
+    pub fn share_workspace<P: AsRef<Path>>(&self, workspace: P) -> Result<()> {
+        share_recursive(workspace.as_ref(), self.gid)
+    }
+}
+
+fn share_recursive(path: &Path, gid: u32) -> Result<()> {
+    lchown(path, None, Some(gid))?;
+    let metadata = path.symlink_metadata()?;
+    if metadata.is_symlink() {
+        return Ok(());
+    }
+    let mode = if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            share_recursive(&entry?.path(), gid)?;
+        }
+        0o750
+    } else if metadata.permissions().mode() & 0o111 != 0 {
+        0o750
+    } else {
+        0o640
+    };
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
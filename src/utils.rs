@@ -1,4 +1,16 @@
-use std::fs;
+use std::{
+    fs,
+    io::{self, Read},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::process::ExitStatusExt,
+    },
+    process::{ChildStderr, ExitStatus},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::io::{unix::AsyncFd, Interest};
 
 pub fn get_memory_usage(pid: u32) -> Option<u64> {
     let statm_path = format!("/proc/{}/statm", pid);
@@ -10,3 +22,334 @@ pub fn get_memory_usage(pid: u32) -> Option<u64> {
     }
     None
 }
+
+/// Longest wait for the processes of a killed group to exit.
+pub const GROUP_EXIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Interval between two checks of whether a killed group is gone.
+pub const GROUP_EXIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Interval between two sweeps of [`reap_orphans`] in a daemon.
+pub const ORPHAN_REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Make this process the reaper of orphaned descendants of its children, so
+/// that leftovers of a submission can be waited for instead of lingering
+/// under init.
+///
+/// Affects the whole process, so it is left to the daemon to call once at
+/// startup, along with [`reap_orphans`] for the orphans it then adopts.
+pub fn become_subreaper() -> io::Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Fields of `/proc/<pid>/stat` telling where a process stands.
+struct ProcessStat {
+    pid: u32,
+    state: char,
+    ppid: u32,
+    pgrp: u32,
+    session: u32,
+}
+
+/// List every process visible in `/proc`.
+fn processes() -> Vec<ProcessStat> {
+    let mut processes = vec![];
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return processes;
+    };
+    for entry in entries.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let Ok(stat) = fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        let mut fields = match stat.rsplit_once(')') {
+            Some((_, rest)) => rest.split_whitespace(),
+            None => continue,
+        };
+        let state = fields.next().and_then(|s| s.chars().next());
+        let mut numbers = fields.map(|s| s.parse::<u32>().ok());
+        let (Some(state), Some(Some(ppid)), Some(Some(pgrp)), Some(Some(session))) =
+            (state, numbers.next(), numbers.next(), numbers.next())
+        else {
+            continue;
+        };
+        processes.push(ProcessStat {
+            pid,
+            state,
+            ppid,
+            pgrp,
+            session,
+        });
+    }
+    processes
+}
+
+/// List the members of a process group with their parent pids.
+fn process_group(pgid: u32) -> Vec<(u32, u32)> {
+    processes()
+        .into_iter()
+        .filter(|process| process.pgrp == pgid)
+        .map(|process| (process.pid, process.ppid))
+        .collect()
+}
+
+/// List a process and all of its descendants by their parent pids.
+pub fn process_tree(pid: u32) -> Vec<u32> {
+    let processes = processes();
+    let mut tree = vec![pid];
+    let mut i = 0;
+    while i < tree.len() {
+        let parent = tree[i];
+        tree.extend(
+            processes
+                .iter()
+                .filter(|process| process.ppid == parent)
+                .map(|process| process.pid),
+        );
+        i += 1;
+    }
+    tree
+}
+
+/// Reap, every `interval`, the exited orphans adopted as a subreaper which
+/// are not waited for by a judge.
+///
+/// Leaves alone the children this process spawned itself, that is the
+/// leaders of the groups of runs and the commands in its own group, whose
+/// statuses their owners wait for.
+pub async fn reap_orphans(interval: Duration) {
+    let me = std::process::id();
+    let (own_group, own_session) = unsafe { (libc::getpgrp() as u32, libc::getsid(0) as u32) };
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        for process in processes() {
+            let spawned = process.pgrp == own_group
+                || (process.pgrp == process.pid && process.session == own_session);
+            if process.state == 'Z' && process.ppid == me && !spawned {
+                let _ = wait4(process.pid, false);
+            }
+        }
+    }
+}
+
+/// Kill every process in the group `pgid`, reaping those reparented to this
+/// process as a subreaper, and tell whether the group is gone.
+///
+/// Does not wait for the killed processes to exit, so that it can be retried
+/// from a future, see [`kill_process_group_and_wait`].
+pub fn kill_process_group(pgid: u32) -> bool {
+    unsafe { libc::kill(-(pgid as i32), libc::SIGKILL) };
+    let me = std::process::id();
+    let members = process_group(pgid);
+    let mut remaining = members.len();
+    for (pid, ppid) in members {
+        if ppid == me && matches!(wait4(pid, false), Ok(Some(_))) {
+            remaining -= 1;
+        }
+    }
+    remaining == 0
+}
+
+/// Kill every process in the group `pgid` and wait until all of them are
+/// gone, like [`kill_process_group`].
+///
+/// Gives up after [`GROUP_EXIT_TIMEOUT`], should a process be stuck in the kernel.
+pub async fn kill_process_group_and_wait(pgid: u32) {
+    let deadline = tokio::time::Instant::now() + GROUP_EXIT_TIMEOUT;
+    while !kill_process_group(pgid) && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(GROUP_EXIT_INTERVAL).await;
+    }
+}
+
+/// Read the user and system CPU time of a process from `/proc/<pid>/stat`.
+pub fn get_cpu_time(pid: u32) -> Option<Duration> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces, so skip past its closing parenthesis
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace();
+    let utime = fields.nth(11)?.parse::<u64>().ok()?;
+    let stime = fields.next()?.parse::<u64>().ok()?;
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) as u64 };
+    Some(Duration::from_millis((utime + stime) * 1000 / ticks))
+}
+
+/// Reap a child with `wait4`, returning its exit status and resource usage.
+///
+/// Returns `None` if `block` is `false` and the child has not exited yet.
+pub fn wait4(pid: u32, block: bool) -> std::io::Result<Option<(ExitStatus, libc::rusage)>> {
+    let mut status = 0;
+    let mut rusage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    let options = if block { 0 } else { libc::WNOHANG };
+    loop {
+        let ret = unsafe { libc::wait4(pid as i32, &mut status, options, &mut rusage) };
+        match ret {
+            0 => return Ok(None),
+            -1 => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            _ => return Ok(Some((ExitStatus::from_raw(status), rusage))),
+        }
+    }
+}
+
+/// Open a pidfd for the given process, registered with the tokio reactor.
+///
+/// The pidfd becomes readable once the process exits. Returns `None` on kernels
+/// without `pidfd_open` support.
+pub fn pidfd_open(pid: u32) -> Option<AsyncFd<OwnedFd>> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if fd < 0 {
+        return None;
+    }
+    AsyncFd::with_interest(
+        unsafe { OwnedFd::from_raw_fd(fd as i32) },
+        Interest::READABLE,
+    )
+    .ok()
+}
+
+/// Standard error of a child, drained without blocking and capped in size.
+///
+/// Draining while the child runs keeps it from blocking on a full pipe.
+pub struct CappedPipe {
+    pipe: AsyncFd<ChildStderr>,
+    buffer: Vec<u8>,
+    limit: usize,
+    truncated: u64,
+}
+
+impl CappedPipe {
+    pub fn new(pipe: ChildStderr, limit: usize) -> io::Result<Self> {
+        let fd = pipe.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Self {
+            pipe: AsyncFd::with_interest(pipe, Interest::READABLE)?,
+            buffer: vec![],
+            limit,
+            truncated: 0,
+        })
+    }
+
+    /// Drain the pipe whenever it becomes readable, so that the child never
+    /// waits for the next sample to write more.
+    pub fn poll_drain(&mut self, cx: &mut Context<'_>) {
+        while let Poll::Ready(Ok(mut guard)) = self.pipe.poll_read_ready_mut(cx) {
+            let mut chunk = [0; 8192];
+            match guard.get_inner_mut().read(&mut chunk) {
+                Ok(0) => return,
+                Ok(n) => {
+                    let kept = n.min(self.limit - self.buffer.len());
+                    self.buffer.extend_from_slice(&chunk[..kept]);
+                    self.truncated += (n - kept) as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    /// Read everything left once the child has exited.
+    pub fn drain(&mut self) {
+        let mut chunk = [0; 8192];
+        loop {
+            match self.pipe.get_mut().read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => {
+                    let kept = n.min(self.limit - self.buffer.len());
+                    self.buffer.extend_from_slice(&chunk[..kept]);
+                    self.truncated += (n - kept) as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// The captured output, ending with a marker if it was truncated.
+    pub fn contents(&self) -> String {
+        let mut contents = String::from_utf8_lossy(&self.buffer).into_owned();
+        if self.truncated > 0 {
+            contents.push_str(&format!("\n[... {} bytes truncated]", self.truncated));
+        }
+        contents
+    }
+}
+
+/// Pass `fd` over a Unix `socket`, such as the one created by
+/// [`ViolationListener::new`](crate::seccomp::ViolationListener::new).
+///
+/// # Safety
+///
+/// Must only be called in a forked child right before `exec`.
+pub unsafe fn send_fd(socket: RawFd, fd: RawFd) -> io::Result<()> {
+    let mut control = [0u64; 4];
+    let mut byte = 0u8;
+    let mut iov = libc::iovec {
+        iov_base: (&mut byte as *mut u8).cast(),
+        iov_len: 1,
+    };
+    let mut msg: libc::msghdr = std::mem::zeroed();
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = libc::CMSG_SPACE(size_of::<RawFd>() as u32) as usize;
+    let cmsg = libc::CMSG_FIRSTHDR(&msg);
+    (*cmsg).cmsg_level = libc::SOL_SOCKET;
+    (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+    (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<RawFd>() as u32) as usize;
+    libc::CMSG_DATA(cmsg).cast::<RawFd>().write_unaligned(fd);
+    if libc::sendmsg(socket, &msg, 0) < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Receive a file descriptor sent by [`send_fd`].
+///
+/// Returns `None` when the child closed the socket without sending one.
+pub fn recv_fd(socket: RawFd) -> io::Result<Option<OwnedFd>> {
+    let mut control = [0u64; 4];
+    let mut byte = 0u8;
+    let mut iov = libc::iovec {
+        iov_base: (&mut byte as *mut u8).cast(),
+        iov_len: 1,
+    };
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = size_of_val(&control);
+    let received = unsafe { libc::recvmsg(socket, &mut msg, libc::MSG_CMSG_CLOEXEC) };
+    if received < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    if received == 0 || cmsg.is_null() {
+        return Ok(None);
+    }
+    unsafe {
+        if (*cmsg).cmsg_level != libc::SOL_SOCKET || (*cmsg).cmsg_type != libc::SCM_RIGHTS {
+            return Ok(None);
+        }
+        let fd = libc::CMSG_DATA(cmsg).cast::<RawFd>().read_unaligned();
+        Ok(Some(OwnedFd::from_raw_fd(fd)))
+    }
+}
@@ -0,0 +1,11 @@
+#include <assert.h>
+#include <stdio.h>
+
+int main()
+{
+    int a, b;
+    scanf("%d%d", &a, &b);
+    assert(a == b);
+    printf("%d\n", a + b);
+    return 0;
+}
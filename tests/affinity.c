@@ -0,0 +1,12 @@
+#define _GNU_SOURCE
+#include <sched.h>
+#include <stdio.h>
+
+int main()
+{
+    // Number of cores the run may use
+    cpu_set_t set;
+    sched_getaffinity(0, sizeof(set), &set);
+    printf("%d\n", CPU_COUNT(&set));
+    return 0;
+}
@@ -0,0 +1,39 @@
+#include <stdio.h>
+
+typedef long long int i64;
+
+// Accepts any number of lines holding the sum, following testlib's conventions
+int main(int argc, char *argv[])
+{
+    if (argc < 5)
+        return 3;
+    FILE *input = fopen(argv[1], "r");
+    FILE *output = fopen(argv[2], "r");
+    FILE *result = fopen(argv[4], "w");
+    if (!input || !output || !result)
+        return 3;
+
+    i64 a, b, sum;
+    if (fscanf(input, "%lld %lld", &a, &b) != 2)
+    {
+        fprintf(result, "FAIL bad input\n");
+        return 3;
+    }
+    int count = 0;
+    while (fscanf(output, "%lld", &sum) == 1)
+    {
+        if (sum != a + b)
+        {
+            fprintf(result, "wrong answer expected %lld, found %lld\n", a + b, sum);
+            return 1;
+        }
+        count++;
+    }
+    if (!feof(output) || count == 0)
+    {
+        fprintf(result, "wrong output format expected a number\n");
+        return 2;
+    }
+    fprintf(result, "ok %d numbers\n", count);
+    return 0;
+}
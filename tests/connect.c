@@ -0,0 +1,18 @@
+#include <arpa/inet.h>
+#include <stdio.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+int main()
+{
+    int port;
+    scanf("%d", &port);
+    struct sockaddr_in addr = {0};
+    addr.sin_family = AF_INET;
+    addr.sin_port = htons(port);
+    addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+    // Whether a connection to the judge's listener could be opened
+    int fd = socket(AF_INET, SOCK_STREAM, 0);
+    printf("%d\n", fd >= 0 && connect(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+    return 0;
+}
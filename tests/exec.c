@@ -0,0 +1,10 @@
+#include <stdio.h>
+#include <unistd.h>
+
+// Runs another program in place of itself
+int main()
+{
+    execl("/bin/true", "true", (char *)NULL);
+    printf("3\n3\n");
+    return 0;
+}
@@ -0,0 +1,8 @@
+#include <stdio.h>
+
+int main()
+{
+    for (;;)
+        puts("3");
+    return 0;
+}
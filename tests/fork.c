@@ -0,0 +1,10 @@
+#include <stdio.h>
+#include <unistd.h>
+
+int main()
+{
+    if (fork() == 0)
+        return 0;
+    printf("3\n3\n");
+    return 0;
+}
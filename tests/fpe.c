@@ -0,0 +1,9 @@
+#include <stdio.h>
+
+int main()
+{
+    int a, b;
+    scanf("%d%d", &a, &b);
+    printf("%d\n", a / (b - 2));
+    return 0;
+}
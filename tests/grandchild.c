@@ -0,0 +1,20 @@
+#include <stdio.h>
+#include <unistd.h>
+
+int main()
+{
+    if (fork() == 0)
+    {
+        // Orphan the grandchild, which outlives its parent
+        if (fork() == 0)
+        {
+            printf("%d\n", getpid());
+            fflush(stdout);
+            for (;;)
+                ;
+        }
+        return 0;
+    }
+    for (;;)
+        ;
+}
@@ -0,0 +1,38 @@
+#include <stdio.h>
+
+typedef long long int i64;
+
+// Hands the numbers of the input to the submission and expects their sum
+// back, following testlib's conventions
+int main(int argc, char *argv[])
+{
+    if (argc < 5)
+        return 3;
+    FILE *input = fopen(argv[1], "r");
+    FILE *output = fopen(argv[2], "w");
+    FILE *result = fopen(argv[4], "w");
+    if (!input || !output || !result)
+        return 3;
+
+    i64 a, b, sum;
+    if (fscanf(input, "%lld %lld", &a, &b) != 2)
+    {
+        fprintf(result, "FAIL bad input\n");
+        return 3;
+    }
+    printf("%lld %lld\n", a, b);
+    fflush(stdout);
+    if (scanf("%lld", &sum) != 1)
+    {
+        fprintf(result, "wrong answer no sum\n");
+        return 1;
+    }
+    fprintf(output, "%lld\n", sum);
+    if (sum != a + b)
+    {
+        fprintf(result, "wrong answer expected %lld, found %lld\n", a + b, sum);
+        return 1;
+    }
+    fprintf(result, "ok sum of %lld and %lld\n", a, b);
+    return 0;
+}
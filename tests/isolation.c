@@ -0,0 +1,10 @@
+#include <stdio.h>
+#include <unistd.h>
+
+int main()
+{
+    // PID 1 of a fresh PID namespace, without access to the host filesystem
+    printf("%d\n", getpid() == 1);
+    printf("%d\n", access("/etc/passwd", F_OK) == 0);
+    return 0;
+}
@@ -0,0 +1,25 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <sched.h>
+#include <stdio.h>
+#include <sys/resource.h>
+#include <unistd.h>
+
+// Touches the limits and affinity of itself, then of init
+int main()
+{
+    int mode;
+    scanf("%d", &mode);
+    struct rlimit limit;
+    cpu_set_t set;
+    if (prlimit(0, RLIMIT_NOFILE, NULL, &limit) || prlimit(getpid(), RLIMIT_NOFILE, NULL, &limit))
+        return 1;
+    if (sched_getaffinity(0, sizeof(set), &set) || sched_setaffinity(0, sizeof(set), &set) ||
+        sched_setaffinity(getpid(), sizeof(set), &set))
+        return 1;
+    if (mode == 0)
+        printf("%s\n", sched_setaffinity(1, sizeof(set), &set) && errno == EPERM ? "EPERM" : "allowed");
+    else
+        printf("%d\n", prlimit(1, RLIMIT_NOFILE, NULL, &limit));
+    return 0;
+}
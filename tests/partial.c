@@ -0,0 +1,40 @@
+#include <stdio.h>
+#include <string.h>
+
+typedef long long int i64;
+
+// Grants a quarter of the score per line holding the sum, with `_pc` as
+// compiled with `TESTSYS` when the answer says "pc", with `quitp` when it says
+// "points" and through the score file otherwise
+int main(int argc, char *argv[])
+{
+    if (argc < 5)
+        return 3;
+    FILE *input = fopen(argv[1], "r");
+    FILE *output = fopen(argv[2], "r");
+    FILE *answer = fopen(argv[3], "r");
+    FILE *result = fopen(argv[4], "w");
+    if (!input || !output || !answer || !result)
+        return 3;
+
+    i64 a, b, sum;
+    char mode[16] = "";
+    if (fscanf(input, "%lld %lld", &a, &b) != 2 || fscanf(answer, "%15s", mode) != 1)
+        return 3;
+    int count = 0;
+    while (fscanf(output, "%lld", &sum) == 1)
+        count += sum == a + b;
+
+    if (strcmp(mode, "pc") == 0)
+        return 50 + 25 * count;
+    if (strcmp(mode, "points") == 0)
+    {
+        fprintf(result, "points %g %d sums\n", count / 4.0, count);
+        return 7;
+    }
+    FILE *score = fopen("score", "w");
+    if (!score)
+        return 3;
+    fprintf(score, "%g\n", count / 4.0);
+    return 0;
+}
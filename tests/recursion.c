@@ -0,0 +1,19 @@
+#include <stdio.h>
+
+void depth(int n, int *parent)
+{
+    int frame[16];
+    frame[0] = 0;
+    if (n > 0)
+        depth(n - 1, frame);
+    *parent = frame[0] + 1;
+}
+
+int main()
+{
+    int a, b, n;
+    scanf("%d%d", &a, &b);
+    depth(1000000, &n);
+    printf("%d\n%d\n", a + b + n - 1000001, a + b + n - 1000001);
+    return 0;
+}
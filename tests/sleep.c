@@ -0,0 +1,7 @@
+#include <unistd.h>
+
+int main()
+{
+    sleep(5);
+    return 0;
+}
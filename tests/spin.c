@@ -0,0 +1,7 @@
+int main()
+{
+    volatile unsigned long i = 0;
+    while (1)
+        i++;
+    return 0;
+}
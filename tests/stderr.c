@@ -0,0 +1,8 @@
+#include <stdio.h>
+
+int main()
+{
+    for (int i = 0; i < 100000; i++)
+        fputs("error\n", stderr);
+    return 1;
+}
@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+use eval_stack::calibration::Calibration;
+
+#[test]
+fn test_calibration_factor() {
+    let reference = Duration::from_millis(250);
+
+    let calibration = Calibration::new(Duration::from_millis(375), reference);
+    assert_eq!(calibration.reference_time, reference);
+    assert_eq!(calibration.factor, 1.5);
+    assert!(!calibration.outlier);
+
+    // Clamped, as no plausible host is that fast or slow
+    let calibration = Calibration::new(Duration::from_millis(80), reference);
+    assert_eq!(calibration.factor, 0.5);
+    assert!(calibration.outlier);
+
+    let calibration = Calibration::new(Duration::from_secs(2), reference);
+    assert_eq!(calibration.factor, 4.0);
+    assert!(calibration.outlier);
+
+    // Left unscaled without a measurement
+    let calibration = Calibration::new(Duration::ZERO, Duration::ZERO);
+    assert_eq!(calibration.factor, 1.0);
+    assert!(calibration.outlier);
+}
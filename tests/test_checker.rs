@@ -0,0 +1,213 @@
+use std::io::Write;
+
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases,
+    checker::Checker,
+    compile::Language,
+    config::{JudgeOptions, SandboxMode, TestCase},
+    judge::JudgeStatus,
+    sandbox::Native,
+};
+
+#[tokio::test]
+async fn test_checker() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("checker_workspace");
+    let tests_path = current_dir.join("tests");
+    let checker = Checker::new(Language::C, tests_path.join("checker.c"));
+
+    // Any number of lines holding the sum is accepted, whatever the answer file says
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default().checker(checker.clone()),
+        &Native,
+        vec![
+            (tests_path.join("1.in"), tests_path.join("1.out")),
+            (tests_path.join("2.in"), tests_path.join("1.out")),
+        ],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted());
+        assert_eq!(result.checker_message.as_deref(), Some("ok 2 numbers"));
+    }
+    assert!(!current_dir.join("checker_workspace.checker").exists());
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("affinity.c"),
+        JudgeOptions::default().checker(checker.clone()),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::WrongAnswer { .. }));
+        assert_eq!(
+            result.checker_message.as_deref(),
+            Some("wrong answer expected 3, found 1")
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_checker_in_namespaces() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("namespaces_checker_workspace");
+    let tests_path = current_dir.join("tests");
+    let checker = Checker::new(Language::C, tests_path.join("checker.c"));
+
+    // The checker writes its result into its workspace, which has to stay writable
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default()
+            .sandbox(SandboxMode::Namespaces)
+            .checker(checker),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted());
+        assert_eq!(result.checker_message.as_deref(), Some("ok 2 numbers"));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_partial_scores() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("partial_workspace");
+    let tests_path = current_dir.join("tests");
+    let checker = Checker::new(Language::C, tests_path.join("partial.c")).partial_base(50);
+    let pc_file = std::env::temp_dir().join("partial_pc.out");
+    let points_file = std::env::temp_dir().join("partial_points.out");
+    let score_file = std::env::temp_dir().join("partial_score.out");
+    std::fs::write(&pc_file, "pc\n")?;
+    std::fs::write(&points_file, "points\n")?;
+    std::fs::write(&score_file, "score\n")?;
+
+    // `test.c` prints the sum twice, worth half of each test
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default().checker(checker).no_fail_fast(),
+        &Native,
+        vec![
+            TestCase::new(tests_path.join("1.in"), pc_file).weight(2.0),
+            TestCase::new(tests_path.join("1.in"), points_file),
+            TestCase::new(tests_path.join("1.in"), score_file),
+        ],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    for result in &results {
+        assert!(matches!(
+            result.status,
+            JudgeStatus::PartiallyAccepted { points } if points == 0.5
+        ));
+    }
+    assert_eq!(results[0].score, 1.0);
+    assert_eq!(results[1].score, 0.5);
+    assert_eq!(
+        results[1].checker_message.as_deref(),
+        Some("points 0.5 2 sums")
+    );
+    assert_eq!(results[2].score, 0.5);
+    assert_eq!(results.score, 2.0);
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![
+            TestCase::new(tests_path.join("1.in"), tests_path.join("1.out")).weight(3.0),
+            TestCase::new(tests_path.join("2.in"), tests_path.join("1.out")).weight(2.0),
+        ],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(results[0].is_accepted());
+    assert_eq!(results.score, 3.0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_testlib_checker() -> Result<()> {
+    let Some(testlib) = std::env::var_os("EVAL_STACK_TESTLIB") else {
+        // Not captured, so that the skip shows
+        writeln!(
+            std::io::stderr(),
+            "test_testlib_checker skipped: set EVAL_STACK_TESTLIB to the path of testlib.h"
+        )?;
+        return Ok(());
+    };
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("testlib_workspace");
+    let tests_path = current_dir.join("tests");
+    // Beside `testlib.h`, which it includes
+    let source_dir = std::env::temp_dir().join("eval-stack-testlib");
+    std::fs::create_dir_all(&source_dir)?;
+    std::fs::copy(testlib, source_dir.join("testlib.h"))?;
+    std::fs::copy(
+        tests_path.join("testlib_checker.cpp"),
+        source_dir.join("checker.cpp"),
+    )?;
+    let pc_file = std::env::temp_dir().join("testlib_pc.out");
+    let points_file = std::env::temp_dir().join("testlib_points.out");
+    std::fs::write(&pc_file, "pc\n")?;
+    std::fs::write(&points_file, "points\n")?;
+
+    // With testlib's own exit codes, `_pc(50)` exiting with 50
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default()
+            .checker(Checker::new(Language::CPP, source_dir.join("checker.cpp")))
+            .no_fail_fast(),
+        &Native,
+        vec![
+            (tests_path.join("1.in"), pc_file),
+            (tests_path.join("1.in"), points_file),
+        ],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    for result in &results {
+        assert!(matches!(
+            result.status,
+            JudgeStatus::PartiallyAccepted { points } if points == 0.5
+        ));
+    }
+
+    Ok(())
+}
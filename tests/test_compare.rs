@@ -0,0 +1,165 @@
+use std::{io, path::Path};
+
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases,
+    compare::{Comparator, Comparison, Mismatch, Verdict},
+    compile::Language,
+    config::{JudgeOptions, TestCase},
+    judge::JudgeStatus,
+    sandbox::Native,
+};
+
+#[derive(Debug)]
+struct AcceptAll;
+
+impl Comparator for AcceptAll {
+    fn compare(&self, _output: &Path, _expected: &Path) -> io::Result<Verdict> {
+        Ok(Verdict::Accepted)
+    }
+}
+
+#[test]
+fn test_comparison_modes() -> Result<()> {
+    let dir = std::env::temp_dir().join("compare_modes");
+    std::fs::create_dir_all(&dir)?;
+    let verdict = |comparison: Comparison, output: &str, expected: &str| -> Result<Verdict> {
+        std::fs::write(dir.join("output"), output)?;
+        std::fs::write(dir.join("expected"), expected)?;
+        Ok(comparison.compare(&dir.join("output"), &dir.join("expected"))?)
+    };
+    let compare = |comparison: Comparison, output: &str, expected: &str| -> Result<bool> {
+        Ok(verdict(comparison, output, expected)? == Verdict::Accepted)
+    };
+
+    assert!(compare(Comparison::Exact, "3\n3\n", "3\n3\n")?);
+    assert!(!compare(Comparison::Exact, "3 \n3\n", "3\n3\n")?);
+
+    assert!(compare(Comparison::Lines, "3 \n3\n\n", "3\n3")?);
+    assert!(!compare(Comparison::Lines, "3 3\n", "3\n3\n")?);
+    assert!(!compare(Comparison::Lines, " 3\n", "3\n")?);
+
+    assert!(compare(Comparison::Tokens, "3 3", "3\n  3\n")?);
+    assert!(!compare(Comparison::Tokens, "3 3 3", "3\n3\n")?);
+
+    assert!(compare(
+        Comparison::CaseInsensitive,
+        "YES\nNo\n",
+        "yes\nno"
+    )?);
+    assert!(!compare(Comparison::Lines, "YES\n", "yes\n")?);
+
+    assert!(compare(Comparison::UnorderedLines, "b\n\na \n", "a\nb\n")?);
+    assert!(!compare(Comparison::UnorderedLines, "a\na\n", "a\nb\n")?);
+
+    assert!(compare(Comparison::Presentation, "3 \n3\n\n", "3\n3")?);
+    assert_eq!(
+        verdict(Comparison::Presentation, "3 3\n", "3\n3\n")?,
+        Verdict::PresentationError
+    );
+    assert_eq!(
+        verdict(Comparison::Presentation, " 3\n3\n", "3\n3\n")?,
+        Verdict::PresentationError
+    );
+    assert!(matches!(
+        verdict(Comparison::Presentation, "3 4\n", "3\n3\n")?,
+        Verdict::WrongAnswer(Some(_))
+    ));
+
+    assert!(compare(
+        Comparison::Custom(std::sync::Arc::new(AcceptAll)),
+        "anything",
+        "3\n"
+    )?);
+
+    // Within 1e-6, absolute near zero and relative for large numbers
+    let float = Comparison::Float {
+        absolute: 1e-6,
+        relative: 1e-6,
+    };
+    assert!(compare(float.clone(), "0.5000000001 YES\n", "0.5 YES\n")?);
+    assert!(compare(float.clone(), "1000000.5", "1000000")?);
+    assert!(!compare(float.clone(), "0.50001", "0.5")?);
+    assert!(!compare(float.clone(), "0.5 yes", "0.5 YES")?);
+    assert!(!compare(Comparison::Lines, "0.5000000001\n", "0.5\n")?);
+
+    assert_eq!(
+        verdict(float.clone(), "1 2\n3 4.5\n", "1 2\n3 4\n")?,
+        Verdict::WrongAnswer(Some(Mismatch {
+            line: 2,
+            expected: Some("4".to_string()),
+            found: Some("4.5".to_string()),
+        }))
+    );
+    assert_eq!(
+        verdict(float, "1\n", "1 2\n")?,
+        Verdict::WrongAnswer(Some(Mismatch {
+            line: 1,
+            expected: Some("2".to_string()),
+            found: None,
+        }))
+    );
+    assert_eq!(
+        verdict(Comparison::Lines, "a\nb\nc\n", "a\nb\n")?,
+        Verdict::WrongAnswer(Some(Mismatch {
+            line: 3,
+            expected: None,
+            found: Some("c".to_string()),
+        }))
+    );
+
+    // Output which is not UTF-8 is a wrong answer, not an error
+    std::fs::write(dir.join("output"), b"3\n\xff\xfe\n")?;
+    std::fs::write(dir.join("expected"), "3\n3\n")?;
+    for comparison in [
+        Comparison::Lines,
+        Comparison::CaseInsensitive,
+        Comparison::Presentation,
+    ] {
+        assert!(matches!(
+            comparison.compare(&dir.join("output"), &dir.join("expected"))?,
+            Verdict::WrongAnswer(Some(Mismatch { line: 2, .. }))
+        ));
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_test_case_comparator() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("compare_workspace");
+    let tests_path = current_dir.join("tests");
+
+    // `test.c` prints the sum on two lines, the answer has it on one
+    let expected_file = std::env::temp_dir().join("compare_tokens.out");
+    std::fs::write(&expected_file, "3 3\n")?;
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default().no_fail_fast(),
+        &Native,
+        vec![
+            TestCase::new(tests_path.join("1.in"), expected_file.clone()),
+            TestCase::new(tests_path.join("1.in"), expected_file.clone())
+                .comparator(Comparison::Tokens),
+            TestCase::new(tests_path.join("1.in"), expected_file.clone())
+                .comparator(Comparison::Presentation),
+        ],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(matches!(
+        results[0].status,
+        JudgeStatus::WrongAnswer { mismatch: Some(_) }
+    ));
+    assert!(results[1].is_accepted());
+    assert!(matches!(results[2].status, JudgeStatus::PresentationError));
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "engine")]
+
+use std::{net::TcpListener, sync::Arc};
+
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases, compile::Language, config::SandboxMode,
+    engine::runtime::submission_options, judge::JudgeStatus, sandbox::Native,
+    scheduler::CoreScheduler, seccomp::SeccompPolicy,
+};
+
+#[tokio::test]
+async fn test_submission_options() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("engine_workspace");
+    let tests_path = current_dir.join("tests");
+    let options = submission_options(Arc::new(CoreScheduler::new(1)?), 1.0);
+
+    // Submissions are sandboxed
+    assert!(matches!(options.sandbox, SandboxMode::Namespaces));
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("fork.c"),
+        options.clone(),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(
+            result.status,
+            JudgeStatus::RestrictedFunction {
+                syscall: Some(libc::SYS_clone)
+            }
+        ));
+    }
+
+    // Even allowed to, they cannot connect anywhere
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let input_file = std::env::temp_dir().join("engine_port.in");
+    std::fs::write(&input_file, listener.local_addr()?.port().to_string())?;
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("connect.c"),
+        options.seccomp_policy(SeccompPolicy::strict().allow_all(&[
+            libc::SYS_socket,
+            libc::SYS_connect,
+            libc::SYS_close,
+        ])),
+        &Native,
+        vec![(&input_file, &tests_path.join("connect.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted())
+    }
+
+    Ok(())
+}
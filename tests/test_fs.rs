@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use eval_stack::{case::run_test_cases, compile::Language, config::JudgeOptions};
+use eval_stack::{case::run_test_cases, compile::Language, config::JudgeOptions, sandbox::Native};
 
 #[tokio::test]
 async fn test_fs() -> Result<()> {
@@ -19,7 +19,9 @@ async fn test_fs() -> Result<()> {
             fail_fast: true,
             no_startup_limits: false,
             unsafe_mode: false,
+            ..Default::default()
         },
+        &Native,
         vec![
             (tests_path.join("1.in"), tests_path.join("1.out")),
             (tests_path.join("2.in"), tests_path.join("2.out")),
@@ -33,10 +35,8 @@ async fn test_fs() -> Result<()> {
         assert!(!result.is_accepted());
         assert!(matches!(
             result.status,
-            eval_stack::judge::JudgeStatus::SystemError {
-                code: -1,
-                stderr: _stderr,
-                signal: 31
+            eval_stack::judge::JudgeStatus::RestrictedFunction {
+                syscall: Some(libc::SYS_utimensat)
             }
         ))
     }
@@ -51,7 +51,9 @@ async fn test_fs() -> Result<()> {
             fail_fast: true,
             no_startup_limits: true,
             unsafe_mode: false,
+            ..Default::default()
         },
+        &Native,
         vec![
             (tests_path.join("1.in"), tests_path.join("1.out")),
             (tests_path.join("2.in"), tests_path.join("2.out")),
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases,
+    checker::Checker,
+    compile::Language,
+    config::{JudgeOptions, SandboxMode},
+    interactor::Interactor,
+    judge::JudgeStatus,
+    sandbox::Native,
+};
+
+#[tokio::test]
+async fn test_interactor() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("interactor_workspace");
+    let tests_path = current_dir.join("tests");
+    let interactor = Interactor::new(Language::C, tests_path.join("interactor.c"));
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default().interactor(interactor.clone()),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(results[0].is_accepted());
+    assert_eq!(
+        results[0].checker_message.as_deref(),
+        Some("ok sum of 1 and 2")
+    );
+    assert!(!current_dir.join("interactor_workspace.interactor").exists());
+
+    // The checker checks what the interactor wrote, a single sum
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default()
+            .interactor(interactor.clone())
+            .checker(Checker::new(Language::C, tests_path.join("checker.c"))),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(results[0].is_accepted());
+    assert_eq!(results[0].checker_message.as_deref(), Some("ok 1 numbers"));
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("affinity.c"),
+        JudgeOptions::default().interactor(interactor.clone()),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(matches!(results[0].status, JudgeStatus::WrongAnswer { .. }));
+    // Exiting without reading, unless the interactor wrote to it first
+    assert!(results[0].checker_message.is_some());
+
+    // The interactor left without a sum rejects the crashed submission
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("abort.c"),
+        JudgeOptions::default().interactor(interactor),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(matches!(results[0].status, JudgeStatus::WrongAnswer { .. }));
+    assert_eq!(
+        results[0].checker_message.as_deref(),
+        Some("wrong answer no sum")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_interactor_verdicts() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("interactor_verdicts_workspace");
+    let tests_path = current_dir.join("tests");
+    let interactor = Interactor::new(Language::C, tests_path.join("interactor.c"));
+
+    // The interactor writes its result into its workspace, which has to stay writable
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default()
+            .sandbox(SandboxMode::Namespaces)
+            .interactor(interactor.clone()),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(results[0].is_accepted());
+    assert_eq!(
+        results[0].checker_message.as_deref(),
+        Some("ok sum of 1 and 2")
+    );
+
+    // Killing the submission leaves the interactor without a sum, which it
+    // rejects, but the limit is the verdict
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("spin.c"),
+        JudgeOptions::default().interactor(interactor.clone()),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(matches!(results[0].status, JudgeStatus::TimeLimitExceeded));
+    assert_eq!(
+        results[0].checker_message.as_deref(),
+        Some("wrong answer no sum")
+    );
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("sleep.c"),
+        JudgeOptions::default()
+            .idle_time_limit(Duration::from_millis(500))
+            .interactor(interactor),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(matches!(
+        results[0].status,
+        JudgeStatus::IdlenessLimitExceeded
+    ));
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use eval_stack::{case::run_test_cases, compile::Language, config::JudgeOptions};
+use eval_stack::{case::run_test_cases, compile::Language, config::JudgeOptions, sandbox::Native};
 
 #[tokio::test]
 async fn test_rust_judge() -> Result<()> {
@@ -14,6 +14,7 @@ async fn test_rust_judge() -> Result<()> {
         &workspace_path,
         &tests_path.join("test.rs"),
         Default::default(),
+        &Native,
         vec![
             (tests_path.join("1.in"), tests_path.join("1.out")),
             (tests_path.join("2.in"), tests_path.join("2.out")),
@@ -37,7 +38,9 @@ async fn test_rust_judge() -> Result<()> {
             fail_fast: true,
             no_startup_limits: false,
             unsafe_mode: false,
+            ..Default::default()
         },
+        &Native,
         vec![
             (tests_path.join("any.in"), tests_path.join("any.out")),
             (tests_path.join("1.out"), tests_path.join("1.in")),
@@ -65,6 +68,7 @@ async fn test_cpp_judge() -> Result<()> {
         &workspace_path,
         &tests_path.join("test.cpp"),
         Default::default(),
+        &Native,
         vec![
             (tests_path.join("1.in"), tests_path.join("1.out")),
             (tests_path.join("2.in"), tests_path.join("2.out")),
@@ -92,6 +96,7 @@ async fn test_c_judge() -> Result<()> {
         &workspace_path,
         &tests_path.join("test.c"),
         Default::default(),
+        &Native,
         vec![
             (tests_path.join("1.in"), tests_path.join("1.out")),
             (tests_path.join("2.in"), tests_path.join("2.out")),
@@ -119,6 +124,7 @@ async fn test_python_judge() -> Result<()> {
         &workspace_path,
         &tests_path.join("test.py"),
         Default::default(),
+        &Native,
         vec![
             (tests_path.join("1.in"), tests_path.join("1.out")),
             (tests_path.join("2.in"), tests_path.join("2.out")),
@@ -146,6 +152,7 @@ async fn test_nodejs_judge() -> Result<()> {
         &workspace_path,
         &tests_path.join("test.mjs"),
         JudgeOptions::default().no_startup_limits(true),
+        &Native,
         vec![
             (tests_path.join("1.in"), tests_path.join("1.out")),
             (tests_path.join("2.in"), tests_path.join("2.out")),
@@ -173,6 +180,7 @@ async fn test_golang_judge() -> Result<()> {
         &workspace_path,
         &tests_path.join("test.go"),
         JudgeOptions::default(),
+        &Native,
         vec![
             (tests_path.join("1.in"), tests_path.join("1.out")),
             (tests_path.join("2.in"), tests_path.join("2.out")),
@@ -200,6 +208,7 @@ async fn test_java_judge() -> Result<()> {
         &workspace_path,
         &tests_path.join("test.java"),
         JudgeOptions::default().no_startup_limits(true),
+        &Native,
         vec![
             (tests_path.join("1.in"), tests_path.join("1.out")),
             (tests_path.join("2.in"), tests_path.join("2.out")),
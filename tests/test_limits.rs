@@ -0,0 +1,264 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases,
+    compile::Language,
+    config::{JudgeOptions, ResourceLimits},
+    judge::JudgeStatus,
+    sandbox::Native,
+};
+
+#[tokio::test]
+async fn test_cpu_time_limit() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("cpu_time_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("spin.c"),
+        JudgeOptions::default().cpu_time_limit(None),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::TimeLimitExceeded));
+        assert!(result.cpu_time >= Duration::from_secs(1));
+    }
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("sleep.c"),
+        JudgeOptions::default().cpu_time_limit(Some(Duration::from_millis(1500))),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::TimeLimitExceeded));
+        assert!(result.time_used >= Duration::from_millis(1500));
+        assert!(result.cpu_time < Duration::from_secs(1));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_speed_factor() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("speed_factor_workspace");
+    let tests_path = current_dir.join("tests");
+
+    // A host twice as fast as the reference gets half of the time limit
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("spin.c"),
+        JudgeOptions::default()
+            .cpu_time_limit(None)
+            .speed_factor(0.5),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::TimeLimitExceeded));
+        assert_eq!(result.speed_factor, Some(0.5));
+        assert!(result.cpu_time >= Duration::from_millis(500));
+        assert!(result.cpu_time < Duration::from_secs(1));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_instruction_limit() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("instruction_limit_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("spin.c"),
+        JudgeOptions::default().instruction_limit(100_000_000, None),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::TimeLimitExceeded));
+        // Stopped by the budget rather than the wall-clock cap
+        assert!(result.instructions.unwrap().count > 100_000_000);
+        assert!(result.time_used < Duration::from_secs(3));
+    }
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default().count_instructions(true),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted());
+        assert!(result.instructions.unwrap().count > 0);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_limit() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("output_limit_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("flood.c"),
+        JudgeOptions::default().limits(ResourceLimits {
+            file_size: 1024 * 1024,
+            ..ResourceLimits::for_language(Language::C)
+        }),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::OutputLimitExceeded));
+    }
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("stderr.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        match result.status {
+            JudgeStatus::RuntimeError { code: 1, stderr } => {
+                assert!(stderr.starts_with("error\n"));
+                assert!(stderr.ends_with("bytes truncated]"));
+                assert!(stderr.len() < 65 * 1024);
+            }
+            status => panic!("unexpected status: {:?}", status),
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stack_limit() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("stack_limit_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("recursion.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted());
+    }
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("recursion.c"),
+        JudgeOptions::default().limits(ResourceLimits::default()),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::SegmentFault { .. }));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_idle_time_limit() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("idle_time_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("sleep.c"),
+        JudgeOptions::default().idle_time_limit(Duration::from_millis(300)),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::IdlenessLimitExceeded));
+        assert!(result.time_used < Duration::from_secs(1));
+    }
+
+    // Busy programs are not idle
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("spin.c"),
+        JudgeOptions::default().idle_time_limit(Duration::from_millis(300)),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::TimeLimitExceeded));
+    }
+
+    Ok(())
+}
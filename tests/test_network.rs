@@ -0,0 +1,58 @@
+use std::net::TcpListener;
+
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases, compile::Language, config::JudgeOptions, judge::JudgeStatus,
+    sandbox::Native, seccomp::SeccompPolicy,
+};
+
+#[tokio::test]
+async fn test_network_isolation() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("network_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let input_file = std::env::temp_dir().join("network_port.in");
+    std::fs::write(&input_file, listener.local_addr()?.port().to_string())?;
+
+    // Let the program reach `connect`, so that only the namespace stops it
+    let policy =
+        SeccompPolicy::strict().allow_all(&[libc::SYS_socket, libc::SYS_connect, libc::SYS_close]);
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("connect.c"),
+        JudgeOptions::default()
+            .seccomp_policy(policy.clone())
+            .isolate_network(true),
+        &Native,
+        vec![(&input_file, &tests_path.join("connect.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted())
+    }
+
+    // Without isolation the same program connects
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("connect.c"),
+        JudgeOptions::default().seccomp_policy(policy),
+        &Native,
+        vec![(&input_file, &tests_path.join("connect.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::WrongAnswer { .. }))
+    }
+
+    Ok(())
+}
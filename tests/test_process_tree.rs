@@ -0,0 +1,51 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases,
+    compile::Language,
+    config::{JudgeOptions, ResourceLimits},
+    judge::JudgeStatus,
+    sandbox::Native,
+    seccomp::SeccompPolicy,
+    utils::become_subreaper,
+};
+
+#[tokio::test]
+async fn test_kill_process_tree() -> Result<()> {
+    // As the daemon does at startup, to reap the orphaned grandchild
+    become_subreaper()?;
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("process_tree_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("grandchild.c"),
+        JudgeOptions::default()
+            .seccomp_policy(SeccompPolicy::for_language(Language::C).allow(libc::SYS_clone))
+            .limits(ResourceLimits {
+                processes: None,
+                ..ResourceLimits::for_language(Language::C)
+            }),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        false,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(result.status, JudgeStatus::TimeLimitExceeded));
+    }
+
+    let output = fs::read_to_string(workspace_path.join("test.out"))?;
+    fs::remove_dir_all(&workspace_path)?;
+    let grandchild = output.trim();
+    assert!(!grandchild.is_empty());
+    // Killed and reaped, not even left as a zombie
+    assert!(!Path::new("/proc").join(grandchild).exists());
+
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::time::Duration;
+
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases,
+    checker::Checker,
+    compile::Language,
+    config::{JudgeOptions, RunAs, SandboxMode},
+    interactor::Interactor,
+    sandbox::Native,
+};
+
+#[tokio::test]
+async fn test_run_as() -> Result<()> {
+    // Switching users needs root
+    if unsafe { libc::geteuid() } != 0 {
+        return Ok(());
+    }
+    let tests_path = std::env::current_dir()?.join("tests");
+    // The workspace must be reachable by the user, which the home of root is not
+    let workspace_path = std::env::temp_dir().join("run_as_workspace");
+
+    let nobody = RunAs::User {
+        uid: 65534,
+        gid: 65534,
+    };
+    let pool = RunAs::Pool { uids: 60000..60001 };
+    for (sandbox, run_as, expected) in [
+        (SandboxMode::Basic, nobody.clone(), "whoami.out"),
+        (SandboxMode::Namespaces, nobody, "whoami.out"),
+        (SandboxMode::Basic, pool.clone(), "whoami_pool.out"),
+        (SandboxMode::Namespaces, pool, "whoami_pool.out"),
+    ] {
+        let results = run_test_cases(
+            Language::C,
+            &workspace_path,
+            &tests_path.join("whoami.c"),
+            JudgeOptions::default().sandbox(sandbox).run_as(run_as),
+            &Native,
+            vec![(tests_path.join("any.in"), tests_path.join(expected))],
+            true,
+        )
+        .await?;
+
+        for result in results {
+            println!("{:?}", result);
+            assert!(result.is_accepted())
+        }
+    }
+
+    // The checker stays the judge's, out of the reach of the submission's user
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default()
+            .run_as(RunAs::Pool { uids: 60000..60001 })
+            .checker(Checker::new(Language::C, tests_path.join("checker.c"))),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        false,
+    )
+    .await?;
+    println!("{:?}", results);
+    assert!(results[0].is_accepted());
+    // Kept by the judge, read by the submission through its group
+    for (path, mode) in [
+        (&workspace_path, 0o750),
+        (&workspace_path.join("out"), 0o750),
+    ] {
+        let metadata = std::fs::metadata(path)?;
+        assert_eq!((metadata.uid(), metadata.gid()), (0, 60000));
+        assert_eq!(metadata.permissions().mode() & 0o777, mode);
+    }
+    let checker_workspace = std::env::temp_dir().join("run_as_workspace.checker");
+    for path in [&checker_workspace, &checker_workspace.join("checker")] {
+        assert_eq!(std::fs::metadata(path)?.uid(), 0);
+    }
+    assert_eq!(
+        std::fs::metadata(&checker_workspace)?.permissions().mode() & 0o777,
+        0o700
+    );
+    std::fs::remove_dir_all(&workspace_path)?;
+    std::fs::remove_dir_all(&checker_workspace)?;
+
+    // So does the interactor, running as the judge
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default()
+            .run_as(RunAs::Pool { uids: 60000..60001 })
+            .interactor(Interactor::new(
+                Language::C,
+                tests_path.join("interactor.c"),
+            )),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        false,
+    )
+    .await?;
+    println!("{:?}", results);
+    assert!(results[0].is_accepted());
+    let interactor_workspace = std::env::temp_dir().join("run_as_workspace.interactor");
+    for path in [
+        &interactor_workspace,
+        &interactor_workspace.join("interactor"),
+    ] {
+        assert_eq!(std::fs::metadata(path)?.uid(), 0);
+    }
+    assert_eq!(
+        std::fs::metadata(&interactor_workspace)?
+            .permissions()
+            .mode()
+            & 0o777,
+        0o700
+    );
+    std::fs::remove_dir_all(&workspace_path)?;
+    std::fs::remove_dir_all(&interactor_workspace)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pool_exhausted() -> Result<()> {
+    let pool = RunAs::Pool { uids: 60100..60101 };
+    let lease = pool.acquire().await?;
+    // Queued until the only uid of the pool is returned
+    let queued = tokio::spawn(async move { pool.acquire().await.map(|l| l.credentials) });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!queued.is_finished());
+    drop(lease);
+    let credentials = tokio::time::timeout(Duration::from_secs(1), queued).await???;
+    assert_eq!((credentials.uid, credentials.gid), (60100, 60100));
+
+    Ok(())
+}
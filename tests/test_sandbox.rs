@@ -0,0 +1,147 @@
+use std::io::Write;
+
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases,
+    compile::Language,
+    config::{JudgeOptions, SandboxMode},
+    namespace::visible_paths,
+    sandbox::{self, Bubblewrap, Native, Nsjail, Sandbox},
+};
+
+#[tokio::test]
+async fn test_namespace_sandbox() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("sandbox_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("isolation.c"),
+        JudgeOptions::default().sandbox(SandboxMode::Namespaces),
+        &Native,
+        vec![(tests_path.join("any.in"), tests_path.join("isolation.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted())
+    }
+
+    let results = run_test_cases(
+        Language::Python,
+        &workspace_path,
+        &tests_path.join("test.py"),
+        JudgeOptions::default().sandbox(SandboxMode::Namespaces),
+        &Native,
+        vec![
+            (tests_path.join("1.in"), tests_path.join("1.out")),
+            (tests_path.join("2.in"), tests_path.join("2.out")),
+        ],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted())
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_wrapping_sandboxes() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("wrapping_workspace");
+    let tests_path = current_dir.join("tests");
+
+    // Only the wrappers installed on this machine can be tested, but the one
+    // selected for a deployment must be there
+    let mut sandboxes: Vec<Box<dyn Sandbox>> = vec![];
+    if let Ok(name) = std::env::var("EVAL_STACK_SANDBOX") {
+        sandboxes.push(sandbox::by_name(&name)?);
+    }
+    // Not captured, so that a skip shows
+    match Bubblewrap::new() {
+        Ok(bwrap) => sandboxes.push(Box::new(bwrap)),
+        Err(_) => writeln!(std::io::stderr(), "bwrap skipped: not installed")?,
+    }
+    match Nsjail::new() {
+        Ok(nsjail) => sandboxes.push(Box::new(nsjail)),
+        Err(_) => writeln!(std::io::stderr(), "nsjail skipped: not installed")?,
+    }
+
+    for sandbox in sandboxes {
+        let results = run_test_cases(
+            Language::C,
+            &workspace_path,
+            &tests_path.join("test.c"),
+            JudgeOptions::default(),
+            sandbox.as_ref(),
+            vec![
+                (tests_path.join("1.in"), tests_path.join("1.out")),
+                (tests_path.join("2.in"), tests_path.join("2.out")),
+            ],
+            true,
+        )
+        .await?;
+
+        for result in results {
+            println!("{:?}", result);
+            assert!(result.is_accepted())
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_missing_wrapper() {
+    // Never falls back to another sandbox
+    assert_eq!(sandbox::by_name("bwrap").is_ok(), Bubblewrap::new().is_ok());
+    assert_eq!(sandbox::by_name("nsjail").is_ok(), Nsjail::new().is_ok());
+    assert!(sandbox::by_name("chroot").is_err());
+}
+
+#[test]
+fn test_runtime_prefix() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("runtime_prefix_workspace");
+    let prefix = std::env::temp_dir().join("eval-stack-runtime");
+    std::fs::create_dir_all(prefix.join("bin"))?;
+    std::fs::create_dir_all(&workspace_path)?;
+    let workspace_path = workspace_path.canonicalize()?;
+    let prefix = prefix.canonicalize()?;
+
+    // The prefix the runtime is installed in
+    let runtime = prefix.join("bin/runtime");
+    std::fs::write(&runtime, "")?;
+    let paths = visible_paths(&workspace_path, &runtime);
+    assert!(paths.contains(&prefix));
+
+    // Never a whole home directory, only the runtime itself, with a home of
+    // its own rather than writing to the real one
+    let home = std::env::temp_dir().join("eval-stack-home");
+    std::fs::create_dir_all(&home)?;
+    let home = home.canonicalize()?;
+    let real_home = std::env::var_os("HOME");
+    std::env::set_var("HOME", &home);
+    let runtime = home.join("eval-stack-runtime");
+    std::fs::write(&runtime, "")?;
+    let paths = visible_paths(&workspace_path, &runtime);
+    match real_home {
+        Some(real_home) => std::env::set_var("HOME", real_home),
+        None => std::env::remove_var("HOME"),
+    }
+    assert!(paths.contains(&runtime));
+    assert!(!paths.iter().any(|path| runtime.parent() == Some(path)));
+
+    std::fs::remove_dir_all(&home)?;
+    std::fs::remove_dir_all(&prefix)?;
+    std::fs::remove_dir_all(&workspace_path)?;
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases, compile::Language, config::JudgeOptions, judge::JudgeStatus,
+    sandbox::Native, scheduler::CoreScheduler, seccomp::SeccompPolicy,
+};
+
+#[tokio::test]
+async fn test_core_slots() -> Result<()> {
+    let scheduler = CoreScheduler::new(1)?;
+    assert_eq!(scheduler.slots(), 1);
+
+    // A second run queues until the first one frees its core
+    let slot = scheduler.acquire().await;
+    let core = slot.core();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), scheduler.acquire())
+            .await
+            .is_err()
+    );
+    drop(slot);
+    assert_eq!(scheduler.acquire().await.core(), core);
+
+    assert!(CoreScheduler::new(0).is_err());
+    assert!(CoreScheduler::new(libc::CPU_SETSIZE as usize + 1).is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_core_pinning() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("scheduler_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("affinity.c"),
+        JudgeOptions::default()
+            .scheduler(Arc::new(CoreScheduler::host()?))
+            .seccomp_policy(
+                SeccompPolicy::for_language(Language::C).allow(libc::SYS_sched_getaffinity),
+            ),
+        &Native,
+        vec![(tests_path.join("any.in"), tests_path.join("affinity.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted())
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pinning_failure() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("pinning_failure_workspace");
+    let tests_path = current_dir.join("tests");
+
+    // A core the host does not have fails the setup, not the judge
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default().scheduler(Arc::new(CoreScheduler::with_cores(vec![1000])?)),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        match result.status {
+            JudgeStatus::SystemError { message } => {
+                assert_eq!(message, "sched_setaffinity failed: EINVAL")
+            }
+            status => panic!("unexpected status: {:?}", status),
+        }
+    }
+    assert!(!workspace_path.exists());
+
+    // Failing to judge at all removes the workspace all the same
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![(tests_path.join("missing.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await;
+    assert!(results.is_err());
+    assert!(!workspace_path.exists());
+
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases, compile::Language, config::JudgeOptions, judge::JudgeStatus,
+    sandbox::Native, seccomp::SeccompPolicy,
+};
+
+#[tokio::test]
+async fn test_seccomp_policy() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("seccomp_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("fork.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(
+            result.status,
+            JudgeStatus::RestrictedFunction {
+                syscall: Some(libc::SYS_clone)
+            }
+        ));
+    }
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("fork.c"),
+        JudgeOptions::default().seccomp_policy(
+            SeccompPolicy::for_language(Language::C)
+                .allow(libc::SYS_fork)
+                .allow(libc::SYS_clone)
+                .allow(libc::SYS_wait4),
+        ),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted());
+    }
+
+    // The JVM reserves far more address space than it uses, so raise
+    // `RLIMIT_AS` instead of disabling the startup limits altogether.
+    let results = run_test_cases(
+        Language::Java,
+        &workspace_path,
+        &tests_path.join("test.java"),
+        JudgeOptions {
+            memory_limit: 64 * 1024 * 1024 * 1024,
+            ..Default::default()
+        },
+        &Native,
+        vec![
+            (tests_path.join("1.in"), tests_path.join("1.out")),
+            (tests_path.join("2.in"), tests_path.join("2.out")),
+        ],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_setup_error() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("setup_error_workspace");
+    let tests_path = current_dir.join("tests");
+
+    // Forbidding `seccomp` itself makes installing the allowlist fail
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default().seccomp_policy(
+            SeccompPolicy::for_language(Language::C).deny(libc::SYS_seccomp, libc::EPERM),
+        ),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        match result.status {
+            JudgeStatus::SystemError { message } => assert_eq!(
+                message,
+                "seccomp(SECCOMP_FILTER_FLAG_NEW_LISTENER) failed: EPERM"
+            ),
+            status => panic!("unexpected status: {:?}", status),
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_syscall_trace() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("trace_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("fork.c"),
+        JudgeOptions::default().trace_syscalls(16),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(
+            result.status,
+            JudgeStatus::RestrictedFunction {
+                syscall: Some(libc::SYS_clone)
+            }
+        ));
+        assert!(result.trace.len() <= 16);
+        let last = result.trace.last().unwrap();
+        assert!(last.rejected && last.nr == libc::SYS_clone);
+        assert!(result.trace[..result.trace.len() - 1]
+            .iter()
+            .all(|syscall| !syscall.rejected));
+    }
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default().trace_syscalls(1024),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(result.is_accepted());
+        // From `exec` to `exit_group`, with the answer written to standard output
+        assert_eq!(result.trace.first().unwrap().nr, libc::SYS_execve);
+        assert_eq!(result.trace.last().unwrap().nr, libc::SYS_exit_group);
+        assert!(result
+            .trace
+            .iter()
+            .any(|syscall| syscall.nr == libc::SYS_write && syscall.args[0] == 1));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_other_processes() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("others_workspace");
+    let tests_path = current_dir.join("tests");
+    let dir = std::env::temp_dir().join("others");
+    std::fs::create_dir_all(&dir)?;
+    for (name, contents) in [("0.in", "0\n"), ("1.in", "1\n"), ("0.out", "EPERM\n")] {
+        std::fs::write(dir.join(name), contents)?;
+    }
+
+    // Limits and affinity of its own, but not of init
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("others.c"),
+        JudgeOptions::default()
+            .seccomp_policy(SeccompPolicy::for_language(Language::C).allow_own_affinity())
+            .no_fail_fast(),
+        &Native,
+        vec![
+            (dir.join("0.in"), dir.join("0.out")),
+            (dir.join("1.in"), dir.join("0.out")),
+        ],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(results[0].is_accepted());
+    assert!(matches!(
+        results[1].status,
+        JudgeStatus::RestrictedFunction {
+            syscall: Some(libc::SYS_prlimit64)
+        }
+    ));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_second_exec() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("exec_workspace");
+    let tests_path = current_dir.join("tests");
+
+    // Only the exec of the submission itself is allowed
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("exec.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    println!("{:?}", results);
+    assert!(matches!(
+        results[0].status,
+        JudgeStatus::RestrictedFunction {
+            syscall: Some(libc::SYS_execve)
+        }
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_kafel() {
+    let policy = SeccompPolicy::default()
+        .allow(libc::SYS_read)
+        .allow_own_affinity()
+        .deny(libc::SYS_clone3, libc::ENOSYS);
+    let affinity = "(a0 & 0xffffffff) != 0x0 && (a0 & 0xffffffff) != 0x1";
+    let own = "((a0 & 0xffffffff) == 0x0) || ((a0 & 0xffffffff) == 0x1)";
+    let args = "a0, a1, a2, a3, a4, a5";
+    // Denied invocations are left out of the allowlist, and `SELF_PID` is bound
+    assert_eq!(
+        policy.to_kafel(1),
+        format!(
+            "POLICY eval_stack {{ \
+             ERRNO(1) {{ SYSCALL[203]({args}) {{ ({affinity}) }} }}, \
+             ERRNO(38) {{ SYSCALL[435] }}, \
+             ALLOW {{ SYSCALL[0], SYSCALL[203]({args}) {{ ({own}) && !(({affinity})) }} }} \
+             }} USE eval_stack DEFAULT TRAP(0)"
+        )
+    );
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use eval_stack::{
+    case::run_test_cases, compile::Language, config::JudgeOptions, judge::JudgeStatus,
+    sandbox::Native,
+};
+
+#[tokio::test]
+async fn test_signal_verdicts() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("signals_workspace");
+    let tests_path = current_dir.join("tests");
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("fpe.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        assert!(matches!(
+            result.status,
+            JudgeStatus::FloatingPointError { .. }
+        ));
+    }
+
+    let results = run_test_cases(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("abort.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![(tests_path.join("1.in"), tests_path.join("1.out"))],
+        true,
+    )
+    .await?;
+
+    for result in results {
+        println!("{:?}", result);
+        match result.status {
+            JudgeStatus::Aborted { stderr } => assert!(stderr.contains("Assertion")),
+            status => panic!("unexpected status: {:?}", status),
+        }
+    }
+
+    Ok(())
+}
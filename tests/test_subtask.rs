@@ -0,0 +1,105 @@
+use anyhow::Result;
+use eval_stack::{
+    compile::Language,
+    config::{JudgeOptions, TestCase},
+    judge::JudgeStatus,
+    sandbox::Native,
+    subtask::{run_subtasks, Scoring, Subtask, SubtaskStatus},
+};
+
+#[tokio::test]
+async fn test_subtasks() -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let workspace_path = current_dir.join("subtask_workspace");
+    let tests_path = current_dir.join("tests");
+    let case = |input: &str, output: &str| (tests_path.join(input), tests_path.join(output));
+
+    let results = run_subtasks(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![
+            Subtask::new("easy", Scoring::Min { score: 30.0 })
+                .test_case(case("1.in", "1.out"))
+                .test_case(case("2.in", "2.out")),
+            // Stops at the wrong answer
+            Subtask::new("hard", Scoring::Min { score: 40.0 })
+                .test_case(case("1.in", "2.out"))
+                .test_case(case("2.in", "2.out")),
+            Subtask::new("sum", Scoring::Sum)
+                .depends_on("easy")
+                .test_case(TestCase::from(case("1.in", "1.out")).weight(10.0))
+                .test_case(TestCase::from(case("1.in", "2.out")).weight(20.0)),
+            Subtask::new("full", Scoring::Min { score: 20.0 })
+                .depends_on("easy")
+                .depends_on("hard")
+                .test_case(case("1.in", "1.out")),
+        ],
+        true,
+    )
+    .await?;
+
+    println!("{:#?}", results);
+    let subtasks = &results.subtasks;
+    assert!(matches!(subtasks[0].status, SubtaskStatus::Accepted));
+    assert_eq!(subtasks[0].score, 30.0);
+    assert!(matches!(
+        subtasks[1].status,
+        SubtaskStatus::Failed {
+            status: JudgeStatus::WrongAnswer { .. }
+        }
+    ));
+    assert_eq!(subtasks[1].results.len(), 1);
+    assert_eq!(subtasks[1].score, 0.0);
+    assert!(matches!(
+        subtasks[2].status,
+        SubtaskStatus::PartiallyAccepted
+    ));
+    assert_eq!(subtasks[2].results.len(), 2);
+    assert_eq!(subtasks[2].score, 10.0);
+    assert!(matches!(
+        &subtasks[3].status,
+        SubtaskStatus::Skipped { dependency } if dependency == "hard"
+    ));
+    assert!(subtasks[3].results.is_empty());
+    assert!(matches!(results.status, JudgeStatus::WrongAnswer { .. }));
+    assert_eq!(results.score, 40.0);
+
+    // Dependencies must be declared first
+    let results = run_subtasks(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![
+            Subtask::new("first", Scoring::Sum)
+                .depends_on("second")
+                .test_case(case("1.in", "1.out")),
+            Subtask::new("second", Scoring::Sum).test_case(case("1.in", "1.out")),
+        ],
+        true,
+    )
+    .await;
+    assert!(results.is_err());
+
+    // An empty subtask would earn its full score
+    let results = run_subtasks(
+        Language::C,
+        &workspace_path,
+        &tests_path.join("test.c"),
+        JudgeOptions::default(),
+        &Native,
+        vec![
+            Subtask::new("easy", Scoring::Min { score: 30.0 }).test_case(case("1.in", "1.out")),
+            Subtask::new("empty", Scoring::Min { score: 70.0 }),
+        ],
+        true,
+    )
+    .await;
+    assert!(results.is_err());
+
+    Ok(())
+}
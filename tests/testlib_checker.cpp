@@ -0,0 +1,17 @@
+#include "testlib.h"
+
+// Grants a quarter of the score per number holding the sum, with `_pc` when
+// the answer says "pc" and with `quitp` otherwise
+int main(int argc, char *argv[])
+{
+    registerTestlibCmd(argc, argv);
+    long long a = inf.readLong(), b = inf.readLong();
+    std::string mode = ans.readToken();
+    int count = 0;
+    while (!ouf.seekEof())
+        count += ouf.readLong() == a + b;
+
+    if (mode == "pc")
+        quitf(_pc(25 * count), "%d sums", count);
+    quitp(count / 4.0, "%d sums", count);
+}
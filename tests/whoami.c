@@ -0,0 +1,8 @@
+#include <stdio.h>
+#include <unistd.h>
+
+int main()
+{
+    printf("%d %d\n", getuid(), getgid());
+    return 0;
+}